@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use bevy::{prelude::*, utils::HashMap};
+
+pub struct AutoPairsPlugin;
+
+impl Plugin for AutoPairsPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.init_resource::<AutoPairs>();
+    }
+}
+
+/// Closers inserted along with their openers in Insert mode, keyed by file extension
+#[derive(Resource, Clone, Debug)]
+pub struct AutoPairs {
+    pub default: Vec<(String, String)>,
+    pub by_extension: HashMap<String, Vec<(String, String)>>,
+}
+
+fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+    list.iter()
+        .map(|(open, close)| (open.to_string(), close.to_string()))
+        .collect()
+}
+
+impl Default for AutoPairs {
+    fn default() -> Self {
+        let brackets = [("(", ")"), ("[", "]"), ("{", "}")];
+        let mut by_extension = HashMap::new();
+        // lifetimes and apostrophes aren't the start of a quote
+        by_extension.insert("rs".into(), pairs(&[brackets[0], brackets[1], brackets[2], ("\"", "\"")]));
+        by_extension.insert("md".into(), pairs(&[brackets[0], brackets[1], brackets[2], ("\"", "\"")]));
+        by_extension.insert("txt".into(), pairs(&[brackets[0], brackets[1], brackets[2]]));
+        Self {
+            default: pairs(&[brackets[0], brackets[1], brackets[2], ("\"", "\""), ("'", "'")]),
+            by_extension,
+        }
+    }
+}
+
+impl AutoPairs {
+    pub fn for_path(&self, path: &Path) -> &[(String, String)] {
+        path.extension()
+            .and_then(|ext| self.by_extension.get(ext.to_string_lossy().as_ref()))
+            .unwrap_or(&self.default)
+    }
+
+    pub fn closer(&self, path: &Path, open: &str) -> Option<&str> {
+        self.for_path(path)
+            .iter()
+            .find(|(pair_open, _)| pair_open == open)
+            .map(|(_, close)| close.as_str())
+    }
+
+    pub fn is_closer(&self, path: &Path, close: &str) -> bool {
+        self.for_path(path).iter().any(|(_, pair_close)| pair_close == close)
+    }
+}
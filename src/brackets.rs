@@ -0,0 +1,393 @@
+use std::path::Path;
+
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+
+use crate::settings::Settings;
+use crate::text_components::{
+    character_str, line_characters, Background, Character, Document, DocumentPath, Highlights, Line, Span
+};
+use crate::{CurrentFocus, CurrentZipper, JumpToChar, ZipperFocus, ZipperType};
+
+const PAIRS: [(&str, &str); 3] = [("(", ")"), ("[", "]"), ("{", "}")];
+const MATCH_COLOR: Color = Color::rgba(0.4, 0.4, 0.6, 0.8);
+const MISMATCH_COLOR: Color = Color::rgb(0.9, 0.2, 0.2);
+
+pub struct BracketPlugin;
+
+impl Plugin for BracketPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (
+                index_brackets,
+                (flag_mismatches, highlight_match, jump_to_match),
+            ).chain())
+            .init_resource::<BracketIndex>()
+            .add_event::<JumpToMatch>();
+    }
+}
+
+/// Which bracket `Character` closes which, in each document that's shown
+#[derive(Resource, Default)]
+pub struct BracketIndex {
+    pairs: HashMap<Entity, Entity>,
+    unmatched: HashSet<Entity>,
+    docs: HashMap<Entity, DocBrackets>,
+}
+
+/// The brackets of every line of a document, kept so an edit only rescans its own lines
+#[derive(Default)]
+struct DocBrackets {
+    lines: HashMap<Entity, LineScan>,
+}
+
+struct LineScan {
+    /// Where the line starts, inside a comment or string left open above or not
+    start: Lex,
+    end: Lex,
+    /// In order, with the index into `PAIRS` and whether it opens
+    brackets: Vec<(Entity, usize, bool)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Lex {
+    Code,
+    BlockComment,
+    /// Index into the syntax's quotes
+    Quote(usize),
+}
+
+/// How a file type writes comments and strings, the brackets inside which don't count
+#[derive(Default)]
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [&'static str],
+    /// Quotes that carry on past the end of the line
+    multiline_quotes: &'static [&'static str],
+    /// `'` only quotes one character, so lifetimes and apostrophes stay code
+    char_literals: bool,
+}
+
+fn syntax_for(path: &Path) -> Syntax {
+    const C_COMMENTS: (&[&str], Option<(&str, &str)>) = (&["//"], Some(("/*", "*/")));
+    let (line_comments, block_comment) = C_COMMENTS;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("rs") => Syntax {
+            line_comments, block_comment, quotes: &["\""], multiline_quotes: &["\""], char_literals: true,
+        },
+        Some("c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "java" | "kt" | "swift" | "zig") => Syntax {
+            line_comments, block_comment, quotes: &["\""], char_literals: true, ..Default::default()
+        },
+        Some("go") => Syntax {
+            line_comments, block_comment, quotes: &["\"", "`"], multiline_quotes: &["`"], char_literals: true,
+        },
+        Some("js" | "jsx" | "ts" | "tsx") => Syntax {
+            line_comments, block_comment, quotes: &["\"", "'", "`"], multiline_quotes: &["`"], ..Default::default()
+        },
+        Some("css") => Syntax { block_comment, quotes: &["\"", "'"], ..Default::default() },
+        Some("json") => Syntax { quotes: &["\""], ..Default::default() },
+        Some("py" | "sh" | "toml" | "yaml" | "yml") => Syntax {
+            line_comments: &["#"], quotes: &["\"", "'"], ..Default::default()
+        },
+        _ => Syntax::default(),
+    }
+}
+
+/// Whether the characters from `i` on spell `token`
+fn spells(chars: &[&str], i: usize, token: &str) -> bool {
+    token.len() <= chars.len().saturating_sub(i)
+        && token.chars().zip(&chars[i..]).all(|(ch, str)| str.chars().eq([ch]))
+}
+
+impl Syntax {
+    /// The brackets outside comments and strings, by position, and where the line leaves off
+    fn scan(&self, chars: &[&str], mut lex: Lex) -> (Vec<usize>, Lex) {
+        let mut brackets = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            match lex {
+                Lex::BlockComment => match self.block_comment {
+                    Some((_, close)) if spells(chars, i, close) => {
+                        lex = Lex::Code;
+                        i += close.len();
+                        continue
+                    },
+                    _ => (),
+                },
+                Lex::Quote(_) if chars[i] == "\\" => i += 1,
+                Lex::Quote(quote) if chars[i] == self.quotes[quote] => lex = Lex::Code,
+                Lex::Quote(_) => (),
+                Lex::Code => {
+                    if self.line_comments.iter().any(|comment| spells(chars, i, comment)) { break }
+                    if let Some((open, _)) = self.block_comment.filter(|(open, _)| spells(chars, i, open)) {
+                        lex = Lex::BlockComment;
+                        i += open.len();
+                        continue
+                    }
+                    if let Some(quote) = self.quotes.iter().position(|quote| chars[i] == *quote) {
+                        lex = Lex::Quote(quote);
+                    } else if self.char_literals && chars[i] == "'" {
+                        i += char_literal_len(&chars[i..]);
+                        continue
+                    } else if is_bracket(chars[i]) {
+                        brackets.push(i);
+                    }
+                },
+            }
+            i += 1;
+        }
+        let lex = match lex {
+            Lex::Quote(quote) if !self.multiline_quotes.contains(&self.quotes[quote]) => Lex::Code,
+            lex => lex,
+        };
+        (brackets, lex)
+    }
+}
+
+/// How many characters a `'x'` or `'\n'` at the start of `chars` covers, just the
+/// `'` when it's a lifetime or an apostrophe
+fn char_literal_len(chars: &[&str]) -> usize {
+    match chars {
+        ["'", "\\", ..] => chars
+            .iter()
+            .skip(2)
+            .take(10)
+            .position(|ch| *ch == "'")
+            .map_or(1, |close| close + 3),
+        ["'", _, "'", ..] => 3,
+        _ => 1,
+    }
+}
+
+impl BracketIndex {
+    pub fn partner(&self, char_id: Entity) -> Option<Entity> {
+        self.pairs.get(&char_id).copied()
+    }
+
+    fn clear(&mut self, doc_id: Entity) {
+        let Some(doc) = self.docs.remove(&doc_id) else { return };
+        for (char_id, ..) in doc.lines.values().flat_map(|scan| &scan.brackets) {
+            self.pairs.remove(char_id);
+            self.unmatched.remove(char_id);
+        }
+    }
+
+    /// Rescans the `dirty` lines, and the ones after them whose comments or
+    /// strings they opened or closed, then pairs the document's brackets again
+    #[allow(clippy::too_many_arguments)]
+    fn update(
+        &mut self,
+        doc_id: Entity,
+        doc_children: &Children,
+        path: &Path,
+        dirty: &HashSet<Entity>,
+        children_q: &Query<&Children>,
+        text_q: &Query<&Text, With<Character>>,
+    ) {
+        let mut doc = self.docs.remove(&doc_id).unwrap_or_default();
+        for (char_id, ..) in doc.lines.values().flat_map(|scan| &scan.brackets) {
+            self.pairs.remove(char_id);
+            self.unmatched.remove(char_id);
+        }
+        doc.lines.retain(|line_id, _| doc_children.contains(line_id));
+
+        let syntax = syntax_for(path);
+        let mut lex = Lex::Code;
+        for line_id in doc_children.iter() {
+            let fresh = doc.lines
+                .get(line_id)
+                .is_some_and(|scan| scan.start == lex && !dirty.contains(line_id));
+            if !fresh {
+                let line_chars = line_characters(*line_id, children_q);
+                let owned = line_chars
+                    .iter()
+                    .map(|id| text_q.get(*id).map(character_str).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                let strs = owned.iter().map(String::as_str).collect::<Vec<_>>();
+                let (positions, end) = syntax.scan(&strs, lex);
+                let brackets = positions
+                    .into_iter()
+                    .filter_map(|i| PAIRS
+                        .iter()
+                        .enumerate()
+                        .find_map(|(pair, (open, close))| match strs[i] {
+                            str if str == *open => Some((line_chars[i], pair, true)),
+                            str if str == *close => Some((line_chars[i], pair, false)),
+                            _ => None,
+                        }))
+                    .collect();
+                doc.lines.insert(*line_id, LineScan { start: lex, end, brackets });
+            }
+            lex = doc.lines[line_id].end;
+        }
+
+        let mut open: Vec<(Entity, usize)> = vec![];
+        for line_id in doc_children.iter() {
+            for (char_id, pair, opens) in doc.lines[line_id].brackets.iter().copied() {
+                if opens {
+                    open.push((char_id, pair));
+                    continue
+                }
+                match open.last() {
+                    Some((open_id, open_pair)) if *open_pair == pair => {
+                        self.pairs.insert(*open_id, char_id);
+                        self.pairs.insert(char_id, *open_id);
+                        open.pop();
+                    },
+                    _ => { self.unmatched.insert(char_id); },
+                }
+            }
+        }
+        self.unmatched.extend(open.into_iter().map(|(open_id, _)| open_id));
+        self.docs.insert(doc_id, doc);
+    }
+}
+
+pub fn is_bracket(str: &str) -> bool {
+    PAIRS.iter().any(|(open, close)| str == *open || str == *close)
+}
+
+/// `%`: jump to the bracket pairing with the one under, or after, the cursor
+#[derive(Event)]
+pub struct JumpToMatch;
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn index_brackets(
+    mut index: ResMut<BracketIndex>,
+    mut shown: RemovedComponents<Background>,
+    hidden_q: Query<Entity, Added<Background>>,
+    // characters come and go through the children of their span, and spans through their line's
+    changed_spans_q: Query<&Parent, (With<Span>, Changed<Children>)>,
+    changed_lines_q: Query<(Entity, &Parent), (With<Line>, Changed<Children>)>,
+    changed_docs_q: Query<Entity, (With<Document>, Changed<Children>)>,
+    doc_q: Query<(&Children, &DocumentPath), (With<Document>, Without<Background>)>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    text_q: Query<&Text, With<Character>>,
+) {
+    // buffers in the background are left out until they're shown again
+    for doc_id in hidden_q.iter() {
+        index.clear(doc_id);
+    }
+    let mut stale = shown.read().collect::<HashSet<_>>();
+    stale.extend(changed_docs_q.iter());
+    let mut dirty = changed_spans_q.iter().map(|line_id| **line_id).collect::<HashSet<_>>();
+    dirty.extend(changed_lines_q.iter().map(|(line_id, _)| line_id));
+    stale.extend(dirty.iter().filter_map(|line_id| parents_q.get(*line_id).ok().map(|doc_id| **doc_id)));
+    for doc_id in stale {
+        match doc_q.get(doc_id) {
+            Ok((doc_children, path)) => index.update(doc_id, doc_children, path, &dirty, &children_q, &text_q),
+            Err(_) => index.clear(doc_id),
+        }
+    }
+}
+
+pub fn flag_mismatches(
+    index: Res<BracketIndex>,
+    settings: Res<Settings>,
+    mut flagged: Local<HashSet<Entity>>,
+    mut text_q: Query<&mut Text, With<Character>>,
+) {
+    if !index.is_changed() && !settings.is_changed() { return }
+    for char_id in flagged.drain() {
+        if let Ok(mut text) = text_q.get_mut(char_id) {
+            text.sections[0].style.color = *settings.foreground;
+        }
+    }
+    for char_id in index.unmatched.iter() {
+        if let Ok(mut text) = text_q.get_mut(*char_id) {
+            text.sections[0].style.color = MISMATCH_COLOR;
+            flagged.insert(*char_id);
+        }
+    }
+}
+
+fn highlight_match(
+    index: Res<BracketIndex>,
+    mut highlighted: Local<Option<Entity>>,
+    new_focus_q: Query<Entity, Added<CurrentFocus>>,
+    focus_q: Query<Entity, (With<CurrentFocus>, With<Character>)>,
+    mut highlights_q: Query<&mut Highlights>,
+) {
+    if !index.is_changed() && new_focus_q.is_empty() { return }
+    if let Some(char_id) = highlighted.take() {
+        if let Ok(mut highlights) = highlights_q.get_mut(char_id) {
+            highlights.bracket = None;
+        }
+    }
+    let Ok(focus) = focus_q.get_single() else { return };
+    let Some(partner) = index.partner(focus) else { return };
+    if let Ok(mut highlights) = highlights_q.get_mut(partner) {
+        highlights.bracket = Some(MATCH_COLOR);
+        *highlighted = Some(partner);
+    }
+}
+
+fn jump_to_match(
+    mut match_evr: EventReader<JumpToMatch>,
+    mut jump_evw: EventWriter<JumpToChar>,
+    index: Res<BracketIndex>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    text_q: Query<&Text, With<Character>>,
+) {
+    if match_evr.read().last().is_none() { return }
+    let Ok((ZipperType::Character, focus)) = curr_zipp_q.get_single() else { return };
+    let Ok(line_id) = parents_q
+        .get(**focus)
+        .and_then(|span_id| parents_q.get(**span_id))
+    else { return };
+    let line_chars = line_characters(**line_id, &children_q);
+    let cursor = line_chars.iter().position(|id| id == &**focus).unwrap_or(0);
+    let bracket = line_chars[cursor..]
+        .iter()
+        .find(|id| text_q.get(**id).is_ok_and(|text| is_bracket(&character_str(text))));
+    if let Some(partner) = bracket.and_then(|id| index.partner(*id)) {
+        jump_evw.send(JumpToChar(partner));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Brackets found on each line, as the characters they are
+    fn scan(file: &str, lines: &[&str]) -> Vec<String> {
+        let syntax = syntax_for(Path::new(file));
+        let mut lex = Lex::Code;
+        lines
+            .iter()
+            .map(|line| {
+                let chars = line.chars().map(String::from).collect::<Vec<_>>();
+                let chars = chars.iter().map(String::as_str).collect::<Vec<_>>();
+                let (positions, end) = syntax.scan(&chars, lex);
+                lex = end;
+                positions.into_iter().map(|i| chars[i]).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn brackets_in_strings_and_chars_dont_count() {
+        assert_eq!(scan("a.rs", &[r#"f("(", ')', "\")", x[0])"#]), ["([])"]);
+        assert_eq!(scan("a.js", &["f('(', `{`)"]), ["()"]);
+    }
+
+    #[test]
+    fn lifetimes_and_apostrophes_stay_code() {
+        assert_eq!(scan("a.rs", &["fn f<'a>(x: &'a str) {}"]), ["(){}"]);
+        assert_eq!(scan("a.txt", &["it's (fine)"]), ["()"]);
+    }
+
+    #[test]
+    fn comments_hide_brackets_across_lines() {
+        assert_eq!(scan("a.rs", &["a(); // b(", "/* c[", "d] */ e{}"]), ["()", "", "{}"]);
+        assert_eq!(scan("a.py", &["f(x)  # )"]), ["()"]);
+    }
+
+    #[test]
+    fn only_some_strings_carry_on_to_the_next_line() {
+        assert_eq!(scan("a.rs", &["\"(", ")\" ()"]), ["", "()"]);
+        assert_eq!(scan("a.c", &["\"(", ")\" ()"]), ["", ")"]);
+    }
+}
@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+
+use crate::explorer::Entry;
+use crate::settings::Settings;
+use crate::structure::StructureNode;
+use crate::text_components::LineNumber;
+use crate::{AppState, CurrentZipper, ZipperFocus, ZipperType};
+
+pub struct BreadcrumbPlugin;
+
+impl Plugin for BreadcrumbPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(Update, (tint_ancestors, show_breadcrumb));
+    }
+}
+
+/// The zipper path, shown at the top of the window while traveling
+#[derive(Component)]
+struct Breadcrumb;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        Breadcrumb,
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.),
+                right: Val::Px(0.),
+                padding: UiRect::all(Val::Px(4.)),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.15, 0.15, 0.15)),
+            z_index: ZIndex::Global(8),
+            ..Default::default()
+        },
+    ));
+}
+
+/// Focuses of the zipper chain from the window down, the current one last
+fn zipper_path(
+    root_zipp_q: &Query<Entity, (With<ZipperType>, Without<Parent>)>,
+    zippers_q: &Query<(&ZipperType, &ZipperFocus, Option<&Children>, Has<CurrentZipper>)>,
+) -> Vec<(ZipperType, Entity)> {
+    let mut path = vec![];
+    let mut next = root_zipp_q.iter().next();
+    while let Some(zipper) = next.take() {
+        let Ok((zip_type, focus, children, current)) = zippers_q.get(zipper) else { break };
+        path.push((*zip_type, **focus));
+        if current { break }
+        next = children.and_then(|children| children.first().copied());
+    }
+    path
+}
+
+type MovedZipper = Or<(Added<CurrentZipper>, Changed<ZipperFocus>)>;
+
+fn path_changed(
+    state: &Res<State<AppState>>,
+    settings: &Res<Settings>,
+    moved_q: &Query<(), MovedZipper>,
+) -> bool {
+    state.is_changed() || settings.is_changed() || !moved_q.is_empty()
+}
+
+/// Tints the document, line and span the current focus sits in while traveling
+fn tint_ancestors(
+    state: Res<State<AppState>>,
+    settings: Res<Settings>,
+    mut tinted: Local<Vec<Entity>>,
+    moved_q: Query<(), MovedZipper>,
+    root_zipp_q: Query<Entity, (With<ZipperType>, Without<Parent>)>,
+    zippers_q: Query<(&ZipperType, &ZipperFocus, Option<&Children>, Has<CurrentZipper>)>,
+    mut bg_q: Query<&mut BackgroundColor>,
+) {
+    if !path_changed(&state, &settings, &moved_q) { return }
+    for focus in tinted.drain(..) {
+        if let Ok(mut bg) = bg_q.get_mut(focus) {
+            *bg = BackgroundColor(Color::NONE);
+        }
+    }
+    if *state.get() != AppState::Travel { return }
+    let mut path = zipper_path(&root_zipp_q, &zippers_q);
+    path.pop();
+    for (zip_type, focus) in path {
+        let tint = match zip_type {
+            ZipperType::Document => settings.document_tint,
+            ZipperType::Line => settings.line_tint,
+            ZipperType::Span => settings.span_tint,
+            // the window keeps its background, characters have no children to focus,
+            // nodes have nothing on screen of their own and directories would tint the
+            // whole sidebar below them
+            ZipperType::Window | ZipperType::Character | ZipperType::Structure | ZipperType::Entry => continue,
+        };
+        if let Ok(mut bg) = bg_q.get_mut(focus) {
+            *bg = BackgroundColor(*tint);
+            tinted.push(focus);
+        }
+    }
+}
+
+/// `Window › Document › Line 42 › Span 3`
+#[allow(clippy::too_many_arguments)]
+fn show_breadcrumb(
+    state: Res<State<AppState>>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    moved_q: Query<(), MovedZipper>,
+    root_zipp_q: Query<Entity, (With<ZipperType>, Without<Parent>)>,
+    zippers_q: Query<(&ZipperType, &ZipperFocus, Option<&Children>, Has<CurrentZipper>)>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    line_number_q: Query<&LineNumber>,
+    node_q: Query<&StructureNode>,
+    entry_q: Query<&Entry>,
+    mut bar_q: Query<(&mut Text, &mut Style), With<Breadcrumb>>,
+) {
+    if !path_changed(&state, &settings, &moved_q) { return }
+    let (mut text, mut style) = bar_q.single_mut();
+    if *state.get() != AppState::Travel {
+        style.display = Display::None;
+        return
+    }
+    let position = |entity: Entity| parents_q
+        .get(entity)
+        .and_then(|parent| children_q.get(**parent))
+        .ok()
+        .and_then(|siblings| siblings.iter().position(|id| *id == entity))
+        .map_or(0, |index| index + 1);
+    let crumbs = zipper_path(&root_zipp_q, &zippers_q)
+        .into_iter()
+        .map(|(zip_type, focus)| match zip_type {
+            ZipperType::Window => "Window".to_string(),
+            ZipperType::Document => "Document".to_string(),
+            ZipperType::Line => format!("Line {}", line_number_q.get(focus).map_or(position(focus), |number| **number)),
+            ZipperType::Span => format!("Span {}", position(focus)),
+            ZipperType::Character => format!("Char {}", position(focus)),
+            ZipperType::Structure => node_q.get(focus).map_or("?".into(), |node| node.label.clone()),
+            ZipperType::Entry => entry_q.get(focus).map_or("?".into(), Entry::name),
+        })
+        .collect::<Vec<_>>();
+
+    // the built-in font only has ASCII
+    let (font, separator) = match settings.font.as_str() {
+        "" => (Handle::default(), " > "),
+        path => (asset_server.load(path.to_string()), " \u{203a} "),
+    };
+    let style_text = TextStyle { font, font_size: settings.font_size, color: *settings.foreground };
+    *text = Text::from_section(crumbs.join(separator), style_text);
+    style.display = Display::Flex;
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use bevy::{
+    prelude::*, transform::TransformSystem, window::RequestRedraw, winit::{UpdateMode, WinitSettings}
+};
+
+use crate::keymap::PendingKeys;
+use crate::settings::Settings;
+use crate::text_components::Character;
+use crate::{AppState, CurrentFocus};
+
+/// How long the caret stays shown, then hidden, while blinking
+const BLINK: Duration = Duration::from_millis(530);
+const BAR_WIDTH: f32 = 2.;
+
+pub struct CaretPlugin;
+
+impl Plugin for CaretPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(Update, blink_wakeups.run_if(resource_changed::<Settings>))
+            .add_systems(PostUpdate, place_caret.after(TransformSystem::TransformPropagate));
+    }
+}
+
+/// Drawn over the focused `Character` in Normal and Insert mode, where the
+/// structural outline would be
+#[derive(Component, Default)]
+struct Caret {
+    /// Where it was last drawn, the blink restarts when this changes
+    at: Option<Rect>,
+    since: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CaretShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        Caret::default(),
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                display: Display::None,
+                ..Default::default()
+            },
+            z_index: ZIndex::Global(5),
+            ..Default::default()
+        },
+    ));
+}
+
+/// Characters have the caret instead of the outline in these modes
+pub fn shows_caret(state: &AppState) -> bool {
+    matches!(state, AppState::Normal | AppState::Insert)
+}
+
+#[allow(clippy::type_complexity)]
+fn place_caret(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    state: Res<State<AppState>>,
+    pending: Res<PendingKeys>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    focus_q: Query<(&Node, &GlobalTransform), (With<CurrentFocus>, With<Character>)>,
+    mut caret_q: Query<(&mut Caret, &mut Style, &mut BackgroundColor)>,
+) {
+    let (mut caret, mut style, mut color) = caret_q.single_mut();
+    let focus = focus_q
+        .get_single()
+        .ok()
+        .filter(|_| shows_caret(state.get()))
+        .map(|(node, transform)| Rect::from_center_size(transform.translation().truncate(), node.size()));
+    let Some(glyph) = focus else {
+        caret.at = None;
+        if style.display != Display::None {
+            style.display = Display::None;
+        }
+        return
+    };
+
+    let now = time.elapsed();
+    if caret.at != Some(glyph) {
+        caret.at = Some(glyph);
+        caret.since = now;
+    }
+    let shape = match state.get() {
+        _ if **pending => CaretShape::Underline,
+        AppState::Insert => CaretShape::Bar,
+        _ => CaretShape::Block,
+    };
+    let rect = match shape {
+        CaretShape::Block => glyph,
+        CaretShape::Bar => Rect::new(glyph.min.x, glyph.min.y, glyph.min.x + BAR_WIDTH, glyph.max.y),
+        CaretShape::Underline => Rect::new(glyph.min.x, glyph.max.y - BAR_WIDTH, glyph.max.x, glyph.max.y),
+    };
+    let alpha = match shape {
+        // the glyph shows through
+        CaretShape::Block => 0.5,
+        _ => 1.,
+    };
+    let hidden = settings.caret_blink
+        && ((now - caret.since).as_millis() / BLINK.as_millis()) % 2 == 1;
+
+    let placed = Style {
+        position_type: PositionType::Absolute,
+        display: if hidden { Display::None } else { Display::Flex },
+        left: Val::Px(rect.min.x),
+        top: Val::Px(rect.min.y),
+        width: Val::Px(rect.width()),
+        height: Val::Px(rect.height()),
+        ..Default::default()
+    };
+    if *style != placed {
+        *style = placed;
+        // laid out on the next frame, which a reactive app has to be asked for
+        redraw_evw.send(RequestRedraw);
+    }
+    let caret_color = settings.cursor.with_a(alpha);
+    if color.0 != caret_color {
+        *color = BackgroundColor(caret_color);
+    }
+}
+
+/// A reactive app only updates on input, so blinking needs it woken up on time
+fn blink_wakeups(settings: Res<Settings>, mut winit: ResMut<WinitSettings>) {
+    let wait = match settings.caret_blink {
+        true => BLINK,
+        false => match WinitSettings::desktop_app().focused_mode {
+            UpdateMode::Reactive { wait } => wait,
+            _ => return,
+        },
+    };
+    winit.focused_mode = UpdateMode::Reactive { wait };
+}
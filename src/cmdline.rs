@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+
+use crate::explorer::ExplorerCommand;
+use crate::grep::GrepCommand;
+use crate::keymap::{dispatch_keys, Action, TypedText};
+use crate::{prompt::prompt_inactive, settings::{SetLocal, Settings}, AppState, Save};
+
+pub struct CommandLinePlugin;
+
+impl Plugin for CommandLinePlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(OnEnter(AppState::Command), open_command_line)
+            .add_systems(Update, (
+                control_command
+                    .run_if(in_state(AppState::Command).and_then(prompt_inactive))
+                    .after(dispatch_keys),
+                show_command_line,
+            ).chain())
+            .init_resource::<CommandLine>()
+            .init_resource::<StatusMessage>();
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CommandLine {
+    input: String,
+    return_to: AppState,
+    /// What the command line opens with next, instead of nothing
+    prefilled: Option<String>,
+}
+
+impl CommandLine {
+    pub fn prefill(&mut self, input: String) {
+        self.prefilled = Some(input);
+    }
+}
+
+/// A command the command line runs, as the command palette lists it
+pub struct ExCommand {
+    pub name: &'static str,
+    /// Shown after the name, empty when it takes none
+    pub args: &'static str,
+    pub description: &'static str,
+}
+
+pub const EX_COMMANDS: &[ExCommand] = &[
+    ExCommand { name: "w!", args: "", description: "Write the document even if its file changed on disk" },
+    ExCommand { name: "set", args: "{option}", description: "Change a setting, or show it with `?`" },
+    ExCommand { name: "setlocal", args: "{option}", description: "Change a setting for this document only" },
+    ExCommand { name: "grep", args: "{pattern}", description: "Search every file in the project" },
+    ExCommand { name: "cnext", args: "", description: "Go to the next grep match" },
+    ExCommand { name: "cprevious", args: "", description: "Go to the previous grep match" },
+    ExCommand { name: "cc", args: "[number]", description: "Go to the numbered grep match, or the current one again" },
+    ExCommand { name: "copen", args: "", description: "Show the list of grep matches" },
+    ExCommand { name: "cclose", args: "", description: "Hide the list of grep matches" },
+    ExCommand { name: "touch", args: "{name}", description: "Create a file next to the explorer entry" },
+    ExCommand { name: "mkdir", args: "{name}", description: "Create a directory next to the explorer entry" },
+    ExCommand { name: "rename", args: "{name}", description: "Rename the explorer entry" },
+    ExCommand { name: "rm", args: "", description: "Delete the explorer entry" },
+    ExCommand { name: "refresh", args: "", description: "Read the explorer's directories again" },
+];
+
+/// Feedback shown in the command line area while it isn't being typed in
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct StatusMessage(pub Option<String>);
+
+#[derive(Component)]
+struct CommandBar;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        CommandBar,
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(0.),
+                left: Val::Px(0.),
+                right: Val::Px(0.),
+                padding: UiRect::all(Val::Px(4.)),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.15, 0.15, 0.15)),
+            z_index: ZIndex::Global(9),
+            ..Default::default()
+        },
+    ));
+}
+
+fn open_command_line(
+    mut transition_evr: EventReader<StateTransitionEvent<AppState>>,
+    mut command_line: ResMut<CommandLine>,
+    mut status: ResMut<StatusMessage>,
+) {
+    if let Some(transition) = transition_evr.read().last() {
+        command_line.return_to = transition.before;
+    }
+    command_line.input = command_line.prefilled.take().unwrap_or_default();
+    **status = None;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn control_command(
+    mut action_evr: EventReader<Action>,
+    mut text_evr: EventReader<TypedText>,
+    mut command_line: ResMut<CommandLine>,
+    mut status: ResMut<StatusMessage>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut save_evw: EventWriter<Save>,
+    mut settings: ResMut<Settings>,
+    mut setlocal_evw: EventWriter<SetLocal>,
+    mut grep_evw: EventWriter<GrepCommand>,
+    mut explorer_evw: EventWriter<ExplorerCommand>,
+) {
+    for TypedText(str) in text_evr.read() {
+        command_line.input.push_str(str);
+    }
+    for action in action_evr.read() {
+        match action {
+            Action::Cancel => {
+                next_state.set(command_line.return_to);
+                return
+            },
+            Action::Execute => {
+                let input = std::mem::take(&mut command_line.input);
+                let (name, bang, args) = parse_command(&input);
+                match name {
+                    "" => (),
+                    "w" | "write" => { save_evw.send(Save { force: bang }); },
+                    "se" | "set" => {
+                        let mut updated = settings.clone();
+                        match updated.set_command(args) {
+                            Ok(shown) => {
+                                **status = shown;
+                                settings.set_if_neq(updated);
+                            },
+                            Err(err) => **status = Some(format!("{err:#}")),
+                        }
+                    },
+                    "setl" | "setlocal" => { setlocal_evw.send(SetLocal(args.to_string())); },
+                    "gr" | "grep" => { grep_evw.send(GrepCommand::Search(args.to_string())); },
+                    "cn" | "cnext" => { grep_evw.send(GrepCommand::Next); },
+                    "cp" | "cprevious" => { grep_evw.send(GrepCommand::Previous); },
+                    "cc" => match args {
+                        "" => { grep_evw.send(GrepCommand::Go(None)); },
+                        number => match number.parse() {
+                            Ok(number) => { grep_evw.send(GrepCommand::Go(Some(number))); },
+                            Err(_) => **status = Some(format!("Not a match number: {number}")),
+                        },
+                    },
+                    "cope" | "copen" => { grep_evw.send(GrepCommand::Show(true)); },
+                    "ccl" | "cclose" => { grep_evw.send(GrepCommand::Show(false)); },
+                    "touch" | "mkdir" if args.is_empty() => **status = Some("Argument required".into()),
+                    "touch" => { explorer_evw.send(ExplorerCommand::Create { name: args.to_string(), dir: false }); },
+                    "mkdir" => { explorer_evw.send(ExplorerCommand::Create { name: args.to_string(), dir: true }); },
+                    "rename" if args.is_empty() => **status = Some("Argument required".into()),
+                    "rename" => { explorer_evw.send(ExplorerCommand::Rename(args.to_string())); },
+                    "rm" => { explorer_evw.send(ExplorerCommand::Delete); },
+                    "refresh" => { explorer_evw.send(ExplorerCommand::Refresh); },
+                    _ => **status = Some(format!("Not an editor command: {input}")),
+                }
+                next_state.set(command_line.return_to);
+                return
+            },
+            Action::Backspace if command_line.input.is_empty() => {
+                next_state.set(command_line.return_to);
+            },
+            Action::Backspace => { command_line.input.pop(); },
+            _ => (),
+        }
+    }
+}
+
+/// Splits `name[!] args` into its parts
+pub fn parse_command(input: &str) -> (&str, bool, &str) {
+    let input = input.trim();
+    let name_end = input
+        .find(|c: char| !c.is_alphanumeric())
+        .unwrap_or(input.len());
+    let (name, rest) = input.split_at(name_end);
+    match rest.strip_prefix('!') {
+        Some(args) => (name, true, args.trim()),
+        None => (name, false, rest.trim()),
+    }
+}
+
+fn show_command_line(
+    state: Res<State<AppState>>,
+    command_line: Res<CommandLine>,
+    status: Res<StatusMessage>,
+    mut bar_q: Query<(&mut Text, &mut Style), With<CommandBar>>,
+) {
+    if !state.is_changed() && !command_line.is_changed() && !status.is_changed() { return }
+    let (mut text, mut style) = bar_q.single_mut();
+    let content = match (state.get(), status.0.as_ref()) {
+        (AppState::Command, _) => Some(format!(":{}", command_line.input)),
+        (_, Some(message)) => Some(message.clone()),
+        _ => None,
+    };
+    match content {
+        Some(content) => {
+            *text = Text::from_section(content, Default::default());
+            style.display = Display::Flex;
+        },
+        None => style.display = Display::None,
+    }
+}
@@ -0,0 +1,611 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::HashMap,
+    window::{PrimaryWindow, RequestRedraw}
+};
+
+use crate::cmdline::StatusMessage;
+use crate::finder::project_root;
+use crate::keymap::{dispatch_keys, Action};
+use crate::prompt::{prompt_inactive, ActivePrompt, Prompt, PromptAnswer};
+use crate::settings::Settings;
+use crate::text_components::{AppWindow, Background, Document, DocumentPath, Modified, OpenFile, WorkingFilePath};
+use crate::{
+    refocus, setup_char_zipper, AppState, CurrentFocus, CurrentZipper, Refocus, RootZipperBundle,
+    ZipperFocus, ZipperType
+};
+
+const DELETE_PROMPT: &str = "explorer-delete";
+/// Percent of the window the sidebar takes up
+const WIDTH: f32 = 20.;
+
+pub struct ExplorerPlugin;
+
+impl Plugin for ExplorerPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(OnEnter(AppState::Normal), leave_explorer.before(setup_char_zipper))
+            .add_systems(OnEnter(AppState::Insert), leave_explorer.before(setup_char_zipper))
+            .add_systems(Update, (
+                // a step of a rebuild only runs the frame after it's asked for
+                sync_explorer.before(dispatch_keys),
+                (toggle_explorer, control_entries.run_if(in_state(AppState::Travel)))
+                    .run_if(prompt_inactive)
+                    .after(dispatch_keys)
+                    .before(refocus),
+                explorer_commands.after(sync_explorer).before(refocus),
+                follow_focus,
+                restyle_labels,
+            ))
+            .init_resource::<ExplorerSync>()
+            .add_event::<ExplorerCommand>();
+    }
+}
+
+/// The sidebar window, listing the project as a tree of `Entry`s
+#[derive(Component, Default)]
+pub struct Explorer {
+    scroll: f32,
+}
+
+/// A file or directory in the `Explorer`. Each has an `EntryLabel` as its first
+/// child, followed by the entries inside it once it's `Expanded`
+#[derive(Component)]
+pub struct Entry {
+    path: PathBuf,
+    is_dir: bool,
+}
+
+impl Entry {
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct Expanded;
+
+#[derive(Component)]
+pub struct EntryLabel;
+
+/// `:touch`, `:mkdir`, `:rename`, `:rm` and `:refresh`, next to the focused entry
+#[derive(Event)]
+pub enum ExplorerCommand {
+    Create { name: String, dir: bool },
+    Rename(String),
+    Delete,
+    Refresh,
+}
+
+/// Rebuilding the tree replaces entries the zipper may be on, so it parks on the
+/// sidebar first, and is put back on an entry once the new ones are spawned
+enum SyncStep {
+    /// Reads the expanded directories again, expanding the ones above `reveal`
+    Rebuild { reveal: Option<PathBuf>, refocus: bool },
+    /// Puts the zipper on `reveal`, or the closest entry above it
+    Refocus(Option<PathBuf>),
+}
+
+#[derive(Resource, Default)]
+struct ExplorerSync {
+    step: Option<SyncStep>,
+    /// Waiting for the delete prompt to be answered
+    deleting: Option<PathBuf>,
+}
+
+#[derive(SystemParam)]
+struct EntryTree<'w, 's> {
+    entry_q: Query<'w, 's, (&'static Entry, Has<Expanded>)>,
+    children_q: Query<'w, 's, &'static Children>,
+    settings: Res<'w, Settings>,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl EntryTree<'_, '_> {
+    fn label(&self, entry: &Entry, expanded: bool) -> Text {
+        let font = match self.settings.font.as_str() {
+            "" => Handle::default(),
+            path => self.asset_server.load(path.to_string()),
+        };
+        let style = TextStyle { font, font_size: self.settings.font_size, color: *self.settings.foreground };
+        let content = match (entry.is_dir, expanded) {
+            (true, true) => format!("- {}/", entry.name()),
+            (true, false) => format!("+ {}/", entry.name()),
+            (false, _) => format!("  {}", entry.name()),
+        };
+        Text::from_section(content, style)
+    }
+
+    fn spawn_entry(&self, commands: &mut Commands, path: PathBuf, is_dir: bool, expanded: bool) -> (Entity, Entity) {
+        let entry = Entry { path, is_dir };
+        let label = commands.spawn((
+            EntryLabel,
+            TextBundle { text: self.label(&entry, expanded), ..Default::default() },
+        )).id();
+        let mut spawned = commands.spawn((
+            entry,
+            NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::left(Val::Px(self.settings.font_size)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ));
+        if expanded {
+            spawned.insert(Expanded);
+        }
+        (spawned.add_child(label).id(), label)
+    }
+
+    fn set_expanded(&self, commands: &mut Commands, id: Entity, entry: &Entry, expanded: bool) {
+        match expanded {
+            true => commands.entity(id).insert(Expanded),
+            false => commands.entity(id).remove::<Expanded>(),
+        };
+        if let Some(label) = self.children_q.get(id).ok().and_then(|children| children.first()) {
+            commands.entity(*label).insert(self.label(entry, expanded));
+        }
+    }
+
+    /// Brings an expanded directory's entries in line with the disk. Entries still
+    /// there keep their entity, so the directories open inside them stay open
+    fn sync_dir(&self, commands: &mut Commands, dir: Entity, label: Entity, path: &Path, reveal: Option<&Path>) {
+        let Ok(listing) = list_dir(path) else { return };
+        let mut existing = self.children_q
+            .get(dir)
+            .map(|children| children
+                .iter()
+                .filter_map(|id| self.entry_q.get(*id).ok().map(|(entry, expanded)| {
+                    (entry.path.clone(), (*id, entry.is_dir, expanded))
+                }))
+                .collect::<HashMap<_, _>>())
+            .unwrap_or_default();
+        let mut kept = vec![label];
+        for (child_path, is_dir) in listing {
+            let revealing = is_dir && reveal.is_some_and(|reveal| reveal != child_path && reveal.starts_with(&child_path));
+            let id = match existing.remove(&child_path) {
+                Some((id, was_dir, expanded)) if was_dir == is_dir => {
+                    if revealing && !expanded {
+                        let entry = Entry { path: child_path.clone(), is_dir };
+                        self.set_expanded(commands, id, &entry, true);
+                    }
+                    if expanded || revealing {
+                        let label = self.children_q.get(id).ok().and_then(|children| children.first().copied());
+                        if let Some(label) = label {
+                            self.sync_dir(commands, id, label, &child_path, reveal);
+                        }
+                    }
+                    id
+                },
+                replaced => {
+                    if let Some((id, ..)) = replaced {
+                        commands.entity(id).despawn_recursive();
+                    }
+                    let (id, label) = self.spawn_entry(commands, child_path.clone(), is_dir, revealing);
+                    if revealing {
+                        self.sync_dir(commands, id, label, &child_path, reveal);
+                    }
+                    id
+                },
+            };
+            kept.push(id);
+        }
+        for (id, ..) in existing.into_values() {
+            commands.entity(id).despawn_recursive();
+        }
+        commands.entity(dir).replace_children(&kept);
+    }
+}
+
+/// A directory's contents, directories first, without `.git`
+fn list_dir(path: &Path) -> io::Result<Vec<(PathBuf, bool)>> {
+    let mut listing = fs::read_dir(path)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name() != ".git")
+        .map(|entry| (entry.path(), entry.file_type().is_ok_and(|kind| kind.is_dir())))
+        .collect::<Vec<_>>();
+    listing.sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then(a.file_name().cmp(&b.file_name())));
+    Ok(listing)
+}
+
+/// The entries of an expanded directory, for the zipper to travel into. The label
+/// stays in the list so indices match the tree's, `control_travel` steps past it
+pub fn explorer_children(
+    focus: Entity,
+    entry_q: &Query<(&Entry, Has<Expanded>)>,
+    children_q: &Query<&Children>,
+) -> Option<(ZipperType, Vec<Entity>)> {
+    let (entry, expanded) = entry_q.get(focus).ok()?;
+    let children = match (entry.is_dir && expanded, children_q.get(focus)) {
+        (true, Ok(children)) if children.len() > 1 => children.to_vec(),
+        _ => vec![],
+    };
+    Some((ZipperType::Entry, children))
+}
+
+fn setup(mut commands: Commands) {
+    let label = commands.spawn((EntryLabel, TextBundle::default())).id();
+    commands.spawn((
+        Explorer::default(),
+        AppWindow,
+        Expanded,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.),
+                left: Val::Px(0.),
+                width: Val::Percent(WIDTH),
+                min_height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.)),
+                overflow: Overflow::clip_x(),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.12, 0.12, 0.12)),
+            ..Default::default()
+        },
+    )).add_child(label);
+}
+
+/// Shows the sidebar and travels into it, reading the tree again and revealing the
+/// open file. From inside the sidebar it hides it and goes back to the document
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn toggle_explorer(
+    mut commands: Commands,
+    mut action_evr: EventReader<Action>,
+    mut sync: ResMut<ExplorerSync>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    file_path: Res<WorkingFilePath>,
+    root_zipp_q: Query<&ZipperFocus, (With<ZipperType>, Without<Parent>)>,
+    mut explorer_q: Query<(Entity, &mut Style, Has<Entry>), With<Explorer>>,
+    mut window_q: Query<&mut Style, (With<AppWindow>, Without<Parent>, Without<Explorer>)>,
+    doc_q: Query<Entity, (With<Document>, Without<Background>)>,
+) {
+    for action in action_evr.read() {
+        if *action != Action::ToggleExplorer { continue }
+        let (sidebar, mut style, populated) = explorer_q.single_mut();
+        let mut window_style = window_q.single_mut();
+        if root_zipp_q.get_single().is_ok_and(|focus| **focus == sidebar) {
+            style.display = Display::None;
+            window_style.left = Val::Px(0.);
+            window_style.width = Val::Percent(100.);
+            if let Ok(document) = doc_q.get_single() {
+                refocus_evw.send(Refocus(document));
+            }
+            continue
+        }
+        style.display = Display::Flex;
+        window_style.left = Val::Percent(WIDTH);
+        window_style.width = Val::Percent(100. - WIDTH);
+        if !populated {
+            commands.entity(sidebar).insert(Entry { path: project_root(&file_path), is_dir: true });
+        }
+        next_state.set(AppState::Travel);
+        let open = file_path.canonicalize().ok();
+        sync.step = Some(SyncStep::Rebuild { reveal: open, refocus: true });
+        redraw_evw.send(RequestRedraw);
+    }
+}
+
+fn delete_prompt(entry: &Entry) -> Prompt {
+    let contents = if entry.is_dir { "/ and everything in it" } else { "" };
+    Prompt {
+        tag: DELETE_PROMPT,
+        message: format!("Delete {}{contents}?", entry.name()),
+        choices: vec![('y', "yes".into()), ('n', "no".into())],
+    }
+}
+
+/// Enter opens a file or expands a directory, `x` asks to delete the entry
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn control_entries(
+    mut commands: Commands,
+    mut action_evr: EventReader<Action>,
+    mut open_evw: EventWriter<OpenFile>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut prompt: ResMut<ActivePrompt>,
+    mut sync: ResMut<ExplorerSync>,
+    tree: EntryTree,
+    curr_zipp_q: Query<&ZipperFocus, With<CurrentZipper>>,
+    explorer_q: Query<(), With<Explorer>>,
+    doc_q: Query<(Entity, &DocumentPath), (With<Document>, Without<Background>)>,
+) {
+    for action in action_evr.read() {
+        let Ok(focus) = curr_zipp_q.get_single() else { continue };
+        let Ok((entry, expanded)) = tree.entry_q.get(**focus) else { continue };
+        // the sidebar itself is the project's directory
+        if explorer_q.contains(**focus) { continue }
+        match action {
+            Action::Execute if entry.is_dir => {
+                tree.set_expanded(&mut commands, **focus, entry, !expanded);
+                let Ok(children) = tree.children_q.get(**focus) else { continue };
+                match expanded {
+                    true => for id in children.iter().skip(1) {
+                        commands.entity(*id).despawn_recursive();
+                    },
+                    false => tree.sync_dir(&mut commands, **focus, children[0], &entry.path, None),
+                }
+            },
+            Action::Execute => {
+                let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                let shown = doc_q
+                    .get_single()
+                    .ok()
+                    .filter(|(_, path)| canonical(path) == canonical(&entry.path));
+                match shown {
+                    Some((document, _)) => { refocus_evw.send(Refocus(document)); },
+                    None => { open_evw.send(OpenFile { path: entry.path.clone(), position: None, record_jump: false }); },
+                }
+            },
+            Action::DeleteFocus => {
+                **prompt = Some(delete_prompt(entry));
+                sync.deleting = Some(entry.path.clone());
+            },
+            _ => (),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn explorer_commands(
+    mut command_evr: EventReader<ExplorerCommand>,
+    mut answer_evr: EventReader<PromptAnswer>,
+    mut sync: ResMut<ExplorerSync>,
+    mut status: ResMut<StatusMessage>,
+    mut prompt: ResMut<ActivePrompt>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    mut file_path: ResMut<WorkingFilePath>,
+    root_zipp_q: Query<&ZipperFocus, (With<ZipperType>, Without<Parent>)>,
+    curr_zipp_q: Query<&ZipperFocus, With<CurrentZipper>>,
+    explorer_q: Query<(Entity, Option<&Entry>), With<Explorer>>,
+    entry_q: Query<&Entry>,
+    mut docs_q: Query<(&mut DocumentPath, &mut Modified)>,
+) {
+    let (sidebar, root) = explorer_q.single();
+    let root = root.map_or_else(|| project_root(&file_path), |root| root.path.clone());
+    let in_sidebar = root_zipp_q.get_single().is_ok_and(|focus| **focus == sidebar);
+    let focused = curr_zipp_q
+        .get_single()
+        .ok()
+        .filter(|_| in_sidebar)
+        .and_then(|focus| entry_q.get(**focus).ok())
+        .filter(|entry| entry.path != root);
+    let relative = |path: &Path| path.strip_prefix(&root).unwrap_or(path).display().to_string();
+
+    let mut changed = None;
+    for command in command_evr.read() {
+        match command {
+            ExplorerCommand::Create { name, dir } => {
+                let base = match focused {
+                    Some(entry) if entry.is_dir => entry.path.clone(),
+                    Some(entry) => entry.path.parent().map_or(root.clone(), Path::to_path_buf),
+                    None => root.clone(),
+                };
+                let path = base.join(name);
+                let created = match dir {
+                    true => fs::create_dir_all(&path),
+                    false => path
+                        .parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|_| fs::OpenOptions::new().write(true).create_new(true).open(&path).map(drop)),
+                };
+                match created {
+                    Ok(()) => {
+                        **status = Some(format!("Created {}", relative(&path)));
+                        changed = Some(Some(path));
+                    },
+                    Err(err) => **status = Some(format!("Can't create {}: {err}", relative(&path))),
+                }
+            },
+            ExplorerCommand::Rename(name) => {
+                let Some(entry) = focused else {
+                    **status = Some("Travel onto an entry of the explorer to rename it".into());
+                    continue
+                };
+                let Some(target) = entry.path.parent().map(|parent| parent.join(name)) else { continue };
+                if target.exists() {
+                    **status = Some(format!("{} already exists", relative(&target)));
+                    continue
+                }
+                // buffers of the file, or of files inside the directory, follow it
+                let moved = |path: &Path| path
+                    .canonicalize()
+                    .ok()
+                    .and_then(|path| path.strip_prefix(&entry.path).ok().map(|rest| target.join(rest)));
+                let moved_buffers = docs_q
+                    .iter()
+                    .map(|(path, _)| moved(path))
+                    .collect::<Vec<_>>();
+                let moved_working = moved(&file_path);
+                match fs::rename(&entry.path, &target) {
+                    Ok(()) => {
+                        for ((mut path, _), moved) in docs_q.iter_mut().zip(moved_buffers) {
+                            if let Some(moved) = moved {
+                                *path = DocumentPath(moved);
+                            }
+                        }
+                        if let Some(moved) = moved_working {
+                            *file_path = WorkingFilePath(moved);
+                        }
+                        **status = Some(format!("Renamed {} to {}", relative(&entry.path), relative(&target)));
+                        changed = Some(Some(target));
+                    },
+                    Err(err) => **status = Some(format!("Can't rename {}: {err}", relative(&entry.path))),
+                }
+            },
+            ExplorerCommand::Delete => {
+                let Some(entry) = focused else {
+                    **status = Some("Travel onto an entry of the explorer to delete it".into());
+                    continue
+                };
+                **prompt = Some(delete_prompt(entry));
+                sync.deleting = Some(entry.path.clone());
+            },
+            ExplorerCommand::Refresh => changed = Some(focused.map(|entry| entry.path.clone())),
+        }
+    }
+    for answer in answer_evr.read() {
+        if answer.tag != DELETE_PROMPT { continue }
+        let Some(path) = sync.deleting.take() else { continue };
+        if answer.choice != 'y' { continue }
+        // buffers of the file, or of files inside the directory, are only left in memory
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let orphaned = docs_q
+            .iter()
+            .map(|(doc_path, _)| doc_path.canonicalize().is_ok_and(|doc_path| doc_path.starts_with(&canonical)))
+            .collect::<Vec<_>>();
+        let deleted = match path.is_dir() {
+            true => fs::remove_dir_all(&path),
+            false => fs::remove_file(&path),
+        };
+        match deleted {
+            Ok(()) => {
+                for ((_, mut modified), orphaned) in docs_q.iter_mut().zip(&orphaned) {
+                    if *orphaned {
+                        **modified = true;
+                    }
+                }
+                **status = Some(match orphaned.contains(&true) {
+                    false => format!("Deleted {}", relative(&path)),
+                    true => format!("Deleted {}, its open buffers are unsaved now", relative(&path)),
+                });
+                changed = Some(path.parent().map(Path::to_path_buf));
+            },
+            Err(err) => **status = Some(format!("Can't delete {}: {err}", relative(&path))),
+        }
+    }
+
+    let Some(reveal) = changed else { return };
+    if in_sidebar {
+        refocus_evw.send(Refocus(sidebar));
+    }
+    sync.step = Some(SyncStep::Rebuild { reveal, refocus: in_sidebar });
+    redraw_evw.send(RequestRedraw);
+}
+
+fn sync_explorer(
+    mut commands: Commands,
+    mut sync: ResMut<ExplorerSync>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    tree: EntryTree,
+    explorer_q: Query<(Entity, &Entry, &Children), With<Explorer>>,
+    entities_q: Query<(Entity, &Entry)>,
+) {
+    let Some(step) = sync.step.take() else { return };
+    let Ok((sidebar, root, children)) = explorer_q.get_single() else { return };
+    redraw_evw.send(RequestRedraw);
+    match step {
+        SyncStep::Rebuild { reveal, refocus } => {
+            tree.sync_dir(&mut commands, sidebar, children[0], &root.path, reveal.as_deref());
+            if refocus {
+                sync.step = Some(SyncStep::Refocus(reveal));
+            }
+        },
+        SyncStep::Refocus(reveal) => {
+            let by_path = entities_q
+                .iter()
+                .map(|(id, entry)| (entry.path.as_path(), id))
+                .collect::<HashMap<_, _>>();
+            let target = reveal
+                .as_deref()
+                .and_then(|reveal| reveal.ancestors().find_map(|path| by_path.get(path).copied()))
+                .or(children.get(1).copied())
+                .unwrap_or(sidebar);
+            refocus_evw.send(Refocus(target));
+        },
+    }
+}
+
+/// Normal and Insert mode edit the document, so a zipper in the sidebar starts over
+/// from the editor's window, for `setup_char_zipper` to go down to the text
+#[allow(clippy::type_complexity)]
+fn leave_explorer(
+    mut commands: Commands,
+    root_zipp_q: Query<(Entity, &ZipperFocus), (With<ZipperType>, Without<Parent>)>,
+    focus_q: Query<Entity, With<CurrentFocus>>,
+    explorer_q: Query<(), With<Explorer>>,
+    window_q: Query<Entity, (With<AppWindow>, Without<Parent>, Without<Explorer>)>,
+) {
+    let Ok((zipper, root_focus)) = root_zipp_q.get_single() else { return };
+    if !explorer_q.contains(**root_focus) { return }
+    commands.entity(zipper).despawn_recursive();
+    for focus in focus_q.iter() {
+        commands.entity(focus).remove::<CurrentFocus>();
+    }
+    let window = window_q.single();
+    commands.spawn((
+        CurrentZipper,
+        RootZipperBundle::new(ZipperType::Window, window)
+    ));
+    commands.entity(window).insert(CurrentFocus);
+}
+
+/// Scrolls the sidebar to keep the focused entry's label in view
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn follow_focus(
+    settings: Res<Settings>,
+    mut waiting: Local<Option<Entity>>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    curr_zipp_q: Query<&ZipperFocus, (With<CurrentZipper>, Or<(Added<CurrentZipper>, Changed<ZipperFocus>)>)>,
+    entry_q: Query<(), With<Entry>>,
+    children_q: Query<&Children>,
+    nodes_q: Query<(&Node, &GlobalTransform)>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    mut explorer_q: Query<(&mut Explorer, &mut Style)>,
+) {
+    let moved = curr_zipp_q
+        .get_single()
+        .ok()
+        .map(|focus| **focus)
+        .filter(|focus| entry_q.contains(*focus));
+    let Some(focus) = moved.or(waiting.take()) else { return };
+    // a directory's node runs on down past its entries
+    let row = children_q.get(focus).ok().and_then(|children| children.first().copied()).unwrap_or(focus);
+    let Ok((node, transform)) = nodes_q.get(row) else { return };
+    // an entry that was just spawned hasn't been laid out yet
+    if node.size().y == 0. {
+        *waiting = Some(focus);
+        redraw_evw.send(RequestRedraw);
+        return
+    }
+    let Ok(window) = window_q.get_single() else { return };
+    let Ok((mut explorer, mut style)) = explorer_q.get_single_mut() else { return };
+    let row_height = node.size().y;
+    let row_top = transform.translation().y - row_height / 2.;
+    let view_height = window.height();
+    let margin = (settings.scrolloff as f32 * row_height).min(((view_height - row_height) / 2.).max(0.));
+    let shift = if row_top < margin {
+        margin - row_top
+    } else if row_top + row_height > view_height - margin {
+        view_height - margin - row_top - row_height
+    } else {
+        return
+    };
+    explorer.scroll = (explorer.scroll + shift).min(0.);
+    style.top = Val::Px(explorer.scroll);
+}
+
+fn restyle_labels(
+    mut commands: Commands,
+    tree: EntryTree,
+    labels_q: Query<(Entity, &Parent), With<EntryLabel>>,
+) {
+    if !tree.settings.is_changed() { return }
+    for (label, parent) in labels_q.iter() {
+        let Ok((entry, expanded)) = tree.entry_q.get(**parent) else { continue };
+        commands.entity(label).insert(tree.label(entry, expanded));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{fs, path::Path, time::{Duration, SystemTime}};
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use similar::{ChangeTag, TextDiff};
+
+use crate::cmdline::StatusMessage;
+use crate::prompt::{ActivePrompt, Prompt, PromptAnswer};
+use crate::text_components::{
+    document_text, Background, Character, Document, Line, Modified, ReplaceContent, Span, WorkingFilePath
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const CONFLICT_PROMPT: &str = "file-changed";
+
+pub struct FileWatchPlugin;
+
+impl Plugin for FileWatchPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(PostStartup, stamp_documents)
+            .add_systems(Update, (
+                poll_file.run_if(on_timer(POLL_INTERVAL)),
+                resolve_conflict,
+            ));
+    }
+}
+
+/// What the file looked like on disk the last time it was read or written
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct DiskStamp(pub Option<FileStamp>);
+
+impl DiskStamp {
+    /// The file changed on disk since we last read or wrote it
+    pub fn is_stale(&self, path: &Path) -> bool {
+        match FileStamp::read(path) {
+            Some(current) => self.0 != Some(current),
+            None => false,
+        }
+    }
+}
+
+#[derive(Component)]
+struct DiffPanel;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        DiffPanel,
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.),
+                left: Val::Px(0.),
+                right: Val::Px(0.),
+                max_height: Val::Percent(80.),
+                padding: UiRect::all(Val::Px(4.)),
+                overflow: Overflow::clip(),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.05, 0.05, 0.05)),
+            z_index: ZIndex::Global(10),
+            ..Default::default()
+        },
+    ));
+}
+
+fn stamp_documents(
+    mut commands: Commands,
+    file_path: Res<WorkingFilePath>,
+    doc_q: Query<Entity, With<Document>>,
+) {
+    for document in doc_q.iter() {
+        commands.entity(document).insert(DiskStamp(FileStamp::read(&file_path)));
+    }
+}
+
+fn conflict_prompt(path: &Path) -> Prompt {
+    Prompt {
+        tag: CONFLICT_PROMPT,
+        message: format!("{} changed on disk and has unsaved changes.", path.display()),
+        choices: vec![
+            ('r', "reload".into()),
+            ('k', "keep mine".into()),
+            ('d', "diff".into()),
+        ],
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn poll_file(
+    file_path: Res<WorkingFilePath>,
+    mut prompt: ResMut<ActivePrompt>,
+    mut status: ResMut<StatusMessage>,
+    mut replace_evw: EventWriter<ReplaceContent>,
+    mut doc_q: Query<(Entity, &Modified, &mut DiskStamp), (With<Document>, Without<Background>)>,
+    mut notified: Local<Option<FileStamp>>,
+) {
+    if prompt.is_some() { return }
+    let Some(current) = FileStamp::read(&file_path) else { return };
+    for (document, modified, mut stamp) in doc_q.iter_mut() {
+        if **stamp == Some(current) { continue }
+        if !**modified {
+            let Ok(content) = fs::read_to_string(&**file_path) else { continue };
+            replace_evw.send(ReplaceContent { document, content });
+            **stamp = Some(current);
+            **status = Some(format!("{} reloaded, it changed on disk", file_path.display()));
+        } else if *notified != Some(current) {
+            *notified = Some(current);
+            **prompt = Some(conflict_prompt(&file_path));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn resolve_conflict(
+    mut answer_evr: EventReader<PromptAnswer>,
+    mut prompt: ResMut<ActivePrompt>,
+    mut status: ResMut<StatusMessage>,
+    mut replace_evw: EventWriter<ReplaceContent>,
+    mut doc_q: Query<(Entity, &Children, &mut Modified, &mut DiskStamp), (With<Document>, Without<Background>)>,
+    mut panel_q: Query<(&mut Text, &mut Style), With<DiffPanel>>,
+    content_q: Query<&Children, Or<(With<Line>, With<Span>)>>,
+    text_q: Query<&Text, (With<Character>, Without<DiffPanel>)>,
+    file_path: Res<WorkingFilePath>,
+) {
+    for answer in answer_evr.read() {
+        if answer.tag != CONFLICT_PROMPT { continue }
+        let (mut panel_text, mut panel_style) = panel_q.single_mut();
+        panel_style.display = Display::None;
+        let (document, doc_children, mut modified, mut stamp) = doc_q.single_mut();
+        match answer.choice {
+            'r' => {
+                let Ok(content) = fs::read_to_string(&**file_path) else { continue };
+                replace_evw.send(ReplaceContent { document, content });
+                **stamp = FileStamp::read(&file_path);
+                **modified = false;
+            },
+            'k' => {
+                **status = Some("Keeping buffer, use :w! to overwrite the file".into());
+            },
+            'd' => {
+                let disk = fs::read_to_string(&**file_path).unwrap_or_default();
+                let buffer = document_text(doc_children, &content_q, &text_q);
+                *panel_text = diff_text(&disk, &buffer);
+                panel_style.display = Display::Flex;
+                **prompt = Some(conflict_prompt(&file_path));
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Line diff from the file on disk to the buffer, colored like `diff -u`
+fn diff_text(disk: &str, buffer: &str) -> Text {
+    let diff = TextDiff::from_lines(disk, buffer);
+    let mut sections = vec![];
+    for group in diff.grouped_ops(2) {
+        for op in group {
+            for change in diff.iter_changes(&op) {
+                let (sign, color) = match change.tag() {
+                    ChangeTag::Delete => ("-", Color::rgb(0.9, 0.4, 0.4)),
+                    ChangeTag::Insert => ("+", Color::rgb(0.4, 0.9, 0.4)),
+                    ChangeTag::Equal => (" ", Color::GRAY),
+                };
+                let mut line = format!("{sign}{}", change.value());
+                if change.missing_newline() { line.push('\n') }
+                sections.push(TextSection::new(line, TextStyle { color, ..Default::default() }));
+            }
+        }
+        sections.push(TextSection::new("...\n", TextStyle { color: Color::GRAY, ..Default::default() }));
+    }
+    Text::from_sections(sections)
+}
@@ -0,0 +1,497 @@
+use std::{fs, path::{Path, PathBuf}, rc::Rc};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    window::RequestRedraw
+};
+
+use crate::cmdline::StatusMessage;
+use crate::keymap::{dispatch_keys, Action, TypedText};
+use crate::prompt::prompt_inactive;
+use crate::settings::Settings;
+use crate::text_components::{OpenFile, WorkingFilePath};
+use crate::AppState;
+
+/// Stops walking huge trees, the finder only ever shows the best few anyway
+const MAX_FILES: usize = 50_000;
+const LISTED: usize = 20;
+const PREVIEW_LINES: usize = 40;
+const PREVIEW_COLUMNS: usize = 100;
+
+pub struct FinderPlugin;
+
+impl Plugin for FinderPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(OnEnter(AppState::Find), open_finder)
+            .add_systems(Update, (
+                collect_files,
+                control_find
+                    .run_if(in_state(AppState::Find).and_then(prompt_inactive))
+                    .after(dispatch_keys),
+                show_finder,
+            ).chain())
+            .init_resource::<Finder>();
+    }
+}
+
+#[derive(Resource, Default)]
+struct Finder {
+    root: PathBuf,
+    /// Relative to `root`, with `/` between directories
+    files: Vec<String>,
+    /// Reading the project off the UI thread, since big trees take a while
+    walking: Option<Task<Vec<String>>>,
+    query: String,
+    /// Indices into `files`, best match first
+    ranked: Vec<usize>,
+    selected: usize,
+    return_to: AppState,
+}
+
+impl Finder {
+    fn rank(&mut self) {
+        let mut scored = self.files
+            .iter()
+            .enumerate()
+            .filter_map(|(i, file)| fuzzy_score(&self.query, file).map(|score| (score, i)))
+            .collect::<Vec<_>>();
+        scored.sort_by(|(a_score, a), (b_score, b)| b_score
+            .cmp(a_score)
+            .then(self.files[*a].len().cmp(&self.files[*b].len())));
+        self.ranked = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+
+    fn selected_file(&self) -> Option<&str> {
+        self.ranked.get(self.selected).map(|i| self.files[*i].as_str())
+    }
+}
+
+#[derive(Component)]
+struct FinderPanel;
+
+#[derive(Component)]
+struct FinderList;
+
+#[derive(Component)]
+struct FinderPreview;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        FinderPanel,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(10.),
+                bottom: Val::Percent(10.),
+                left: Val::Percent(10.),
+                right: Val::Percent(10.),
+                column_gap: Val::Px(8.),
+                padding: UiRect::all(Val::Px(8.)),
+                overflow: Overflow::clip(),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.12, 0.12, 0.12)),
+            z_index: ZIndex::Global(9),
+            ..Default::default()
+        },
+    )).with_children(|parent| {
+        let column = |width| Style { width: Val::Percent(width), overflow: Overflow::clip(), ..Default::default() };
+        parent.spawn((FinderList, TextBundle { style: column(45.), ..Default::default() }));
+        parent.spawn((FinderPreview, TextBundle { style: column(55.), ..Default::default() }));
+    });
+}
+
+/// The closest directory above `file` that's a git checkout, or else the file's own
+pub fn project_root(file: &Path) -> PathBuf {
+    let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let dir = file.parent().unwrap_or(Path::new(".")).to_path_buf();
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map_or(dir.clone(), Path::to_path_buf)
+}
+
+/// One line of a `.gitignore`
+struct IgnorePattern {
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the path from the `.gitignore`'s directory rather than
+    /// just the name, when there's a `/` before the end
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') { return None }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.trim_start_matches('/').to_string();
+        (!glob.is_empty()).then_some(Self { glob, negated, dir_only, anchored })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir { return false }
+        match self.anchored {
+            true => glob_match(self.glob.as_bytes(), relative.as_bytes()),
+            false => glob_match(self.glob.as_bytes(), relative.rsplit('/').next().unwrap_or(relative).as_bytes()),
+        }
+    }
+}
+
+/// `*` and `?` stay within a directory, `**` crosses them
+fn glob_match(glob: &[u8], text: &[u8]) -> bool {
+    match glob {
+        [] => text.is_empty(),
+        // `a/**/b` matches `a/b` too
+        [b'*', b'*', b'/', rest @ ..] => glob_match(rest, text)
+            || text.iter().enumerate().any(|(i, byte)| *byte == b'/' && glob_match(rest, &text[i + 1..])),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|i| !text[..*i].contains(&b'/'))
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => text.first().is_some_and(|byte| *byte != b'/') && glob_match(rest, &text[1..]),
+        [byte, rest @ ..] => text.first() == Some(byte) && glob_match(rest, &text[1..]),
+    }
+}
+
+/// Every file under `root` that git wouldn't ignore, relative to it
+pub fn walk_project(root: &Path) -> Vec<String> {
+    let mut files = vec![];
+    // directories still to read, with the `.gitignore` patterns of the ones they're in,
+    // shared rather than copied down the tree
+    let mut pending = vec![(root.to_path_buf(), String::new(), vec![])];
+    while let Some((dir, relative, mut patterns)) = pending.pop() {
+        if let Ok(source) = fs::read_to_string(dir.join(".gitignore")) {
+            let list = source.lines().filter_map(IgnorePattern::parse).collect::<Vec<_>>();
+            patterns.push((relative.clone(), Rc::new(list)));
+        }
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        let mut entries = entries.filter_map(Result::ok).collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" { continue }
+            let path = match relative.as_str() {
+                "" => name,
+                dir => format!("{dir}/{name}"),
+            };
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            if is_ignored(&patterns, &path, is_dir) { continue }
+            match is_dir {
+                true => pending.push((entry.path(), path, patterns.clone())),
+                false => files.push(path),
+            }
+            if files.len() >= MAX_FILES { return files }
+        }
+    }
+    files
+}
+
+/// The last pattern matching wins, so a `!pattern` can take back an earlier one
+fn is_ignored(patterns: &[(String, Rc<Vec<IgnorePattern>>)], path: &str, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for (base, list) in patterns {
+        let relative = match base.as_str() {
+            "" => path,
+            base => match path.strip_prefix(base).and_then(|rest| rest.strip_prefix('/')) {
+                Some(rest) => rest,
+                None => continue,
+            },
+        };
+        for pattern in list.iter() {
+            if pattern.matches(relative, is_dir) {
+                ignored = !pattern.negated;
+            }
+        }
+    }
+    ignored
+}
+
+/// Higher is better, `None` unless the letters of `query` appear in order in `candidate`.
+/// Matches that are consecutive, start a word or are in the file name count for more
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    const MATCH: i64 = 16;
+    const WORD_START: i64 = 10;
+    const CONSECUTIVE: i64 = 8;
+    const FILE_NAME: i64 = 4;
+    const GAP: i64 = 3;
+
+    let query = query.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase).collect::<Vec<_>>();
+    if query.is_empty() { return Some(0) }
+    let chars = candidate.chars().collect::<Vec<_>>();
+    let name_start = chars.iter().rposition(|c| *c == '/').map_or(0, |i| i + 1);
+    let bonus = |i: usize| {
+        let word_start = match i.checked_sub(1).map(|prev| chars[prev]) {
+            None => true,
+            Some(prev) => matches!(prev, '/' | '_' | '-' | '.' | ' ')
+                || (prev.is_lowercase() && chars[i].is_uppercase()),
+        };
+        MATCH + if word_start { WORD_START } else { 0 } + if i >= name_start { FILE_NAME } else { 0 }
+    };
+
+    // best score with the query so far ending on each character of the candidate
+    let mut previous: Vec<Option<i64>> = vec![None; chars.len()];
+    for (q, wanted) in query.iter().enumerate() {
+        let mut current = vec![None; chars.len()];
+        let mut best_before: Option<i64> = None;
+        for (i, c) in chars.iter().enumerate() {
+            if c.to_lowercase().eq(std::iter::once(*wanted)) {
+                let start = match q {
+                    0 => Some(0),
+                    _ => {
+                        let after_gap = best_before.map(|score| score - GAP);
+                        let adjacent = i.checked_sub(1).and_then(|prev| previous[prev]).map(|score| score + CONSECUTIVE);
+                        after_gap.max(adjacent)
+                    },
+                };
+                current[i] = start.map(|score| score + bonus(i));
+            }
+            // anything up to the one before this can be followed by a gap
+            if q > 0 {
+                if let Some(score) = i.checked_sub(1).and_then(|prev| previous[prev]) {
+                    best_before = best_before.max(Some(score));
+                }
+            }
+        }
+        previous = current;
+    }
+    previous.into_iter().flatten().max()
+}
+
+fn open_finder(
+    mut transition_evr: EventReader<StateTransitionEvent<AppState>>,
+    mut finder: ResMut<Finder>,
+    mut status: ResMut<StatusMessage>,
+    file_path: Res<WorkingFilePath>,
+) {
+    if let Some(transition) = transition_evr.read().last() {
+        finder.return_to = transition.before;
+    }
+    finder.root = project_root(&file_path);
+    let root = finder.root.clone();
+    finder.walking = Some(AsyncComputeTaskPool::get().spawn(async move { walk_project(&root) }));
+    finder.files.clear();
+    finder.query.clear();
+    finder.rank();
+    **status = None;
+}
+
+/// Takes the files once the walk is done, ranked by whatever's been typed meanwhile
+fn collect_files(
+    state: Res<State<AppState>>,
+    mut finder: ResMut<Finder>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+) {
+    let Some(walk) = finder.walking.as_mut() else { return };
+    // closing the finder drops, and so cancels, the walk
+    if *state.get() != AppState::Find {
+        finder.walking = None;
+        return
+    }
+    let Some(files) = block_on(future::poll_once(walk)) else {
+        // frames only come with input otherwise
+        redraw_evw.send(RequestRedraw);
+        return
+    };
+    finder.walking = None;
+    finder.files = files;
+    finder.rank();
+}
+
+fn control_find(
+    mut action_evr: EventReader<Action>,
+    mut text_evr: EventReader<TypedText>,
+    mut finder: ResMut<Finder>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut open_evw: EventWriter<OpenFile>,
+) {
+    let mut requery = false;
+    for TypedText(text) in text_evr.read() {
+        finder.query.push_str(text);
+        requery = true;
+    }
+    for action in action_evr.read() {
+        match action {
+            Action::Cancel => next_state.set(finder.return_to),
+            Action::Execute => {
+                if let Some(file) = finder.selected_file() {
+                    open_evw.send(OpenFile { path: finder.root.join(file), position: None, record_jump: false });
+                }
+                next_state.set(finder.return_to);
+            },
+            Action::Backspace => requery |= finder.query.pop().is_some(),
+            Action::SelectNext => {
+                finder.selected = (finder.selected + 1).min(finder.ranked.len().saturating_sub(1));
+            },
+            Action::SelectPrevious => finder.selected = finder.selected.saturating_sub(1),
+            _ => (),
+        }
+    }
+    if requery {
+        finder.rank();
+    }
+}
+
+/// The first lines of a file, or why they can't be shown
+fn preview(path: &Path) -> String {
+    let Ok(bytes) = fs::read(path) else { return "(can't read file)".into() };
+    if bytes.contains(&0) { return "(binary file)".into() }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(|line| line.replace('\t', "    ").chars().take(PREVIEW_COLUMNS).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_finder(
+    state: Res<State<AppState>>,
+    finder: Res<Finder>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut shown_preview: Local<Option<String>>,
+    mut panel_q: Query<&mut Style, With<FinderPanel>>,
+    mut list_q: Query<&mut Text, (With<FinderList>, Without<FinderPreview>)>,
+    mut preview_q: Query<&mut Text, (With<FinderPreview>, Without<FinderList>)>,
+) {
+    if !state.is_changed() && !finder.is_changed() { return }
+    let mut panel_style = panel_q.single_mut();
+    if *state.get() != AppState::Find {
+        panel_style.display = Display::None;
+        *shown_preview = None;
+        return
+    }
+    panel_style.display = Display::Flex;
+
+    let font = match settings.font.as_str() {
+        "" => Handle::default(),
+        path => asset_server.load(path.to_string()),
+    };
+    let style = |alpha: f32| TextStyle {
+        font: font.clone(),
+        font_size: settings.font_size,
+        color: settings.foreground.with_a(alpha),
+    };
+    // scrolls the list along with the selection
+    let first = finder.selected.saturating_sub(LISTED - 1);
+    let count = match finder.walking {
+        Some(_) => "  reading files...\n".to_string(),
+        None => format!("  {}/{}\n", finder.ranked.len(), finder.files.len()),
+    };
+    let mut sections = vec![
+        TextSection::new(format!("> {}\n", finder.query), style(1.)),
+        TextSection::new(count, style(0.5)),
+    ];
+    for (i, file_index) in finder.ranked.iter().enumerate().skip(first).take(LISTED) {
+        let (marker, alpha) = if i == finder.selected { ("> ", 1.) } else { ("  ", 0.7) };
+        sections.push(TextSection::new(format!("{marker}{}\n", finder.files[*file_index]), style(alpha)));
+    }
+    *list_q.single_mut() = Text::from_sections(sections);
+
+    let selected = finder.selected_file().map(str::to_string);
+    if *shown_preview != selected || state.is_changed() {
+        let content = selected.as_ref().map_or(String::new(), |file| preview(&finder.root.join(file)));
+        *preview_q.single_mut() = Text::from_section(content, style(0.8));
+        *shown_preview = selected;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(gitignore: &str, path: &str, is_dir: bool) -> bool {
+        let list = gitignore.lines().filter_map(IgnorePattern::parse).collect::<Vec<_>>();
+        is_ignored(&[(String::new(), Rc::new(list))], path, is_dir)
+    }
+
+    #[test]
+    fn globs_stay_within_a_directory() {
+        assert!(glob_match(b"*.rs", b"main.rs"));
+        assert!(!glob_match(b"*.rs", b"src/main.rs"));
+        assert!(glob_match(b"src/?ain.rs", b"src/main.rs"));
+        assert!(!glob_match(b"src?main.rs", b"src/main.rs"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(glob_match(b"a/**/b", b"a/b"));
+        assert!(glob_match(b"a/**/b", b"a/x/y/b"));
+        assert!(!glob_match(b"a/**/b", b"a/xb"));
+        assert!(glob_match(b"logs/**", b"logs/2024/jan.txt"));
+    }
+
+    #[test]
+    fn unanchored_patterns_match_the_name_anywhere() {
+        assert!(ignored("*.log", "debug.log", false));
+        assert!(ignored("*.log", "deep/down/debug.log", false));
+        assert!(ignored("target", "crates/app/target", true));
+    }
+
+    #[test]
+    fn anchored_patterns_match_from_the_gitignore() {
+        assert!(ignored("/target", "target", true));
+        assert!(!ignored("/target", "crates/target", true));
+        assert!(ignored("docs/*.md", "docs/intro.md", false));
+        assert!(!ignored("docs/*.md", "src/docs/intro.md", false));
+        assert!(ignored("**/build", "a/b/build", true));
+        assert!(ignored("**/build", "build", true));
+    }
+
+    #[test]
+    fn dir_only_patterns_skip_files() {
+        assert!(ignored("build/", "build", true));
+        assert!(!ignored("build/", "build", false));
+    }
+
+    #[test]
+    fn later_negation_takes_a_match_back() {
+        let gitignore = "*.log\n!keep.log";
+        assert!(ignored(gitignore, "debug.log", false));
+        assert!(!ignored(gitignore, "keep.log", false));
+        assert!(ignored("!keep.log\n*.log", "keep.log", false));
+        assert!(!ignored("\\!bang", "bang", false));
+        assert!(ignored("\\!bang", "!bang", false));
+    }
+
+    #[test]
+    fn nested_gitignores_match_from_their_directory() {
+        let list = Rc::new(["/out"].into_iter().filter_map(IgnorePattern::parse).collect::<Vec<_>>());
+        let patterns = [("web".to_string(), list)];
+        assert!(is_ignored(&patterns, "web/out", true));
+        assert!(!is_ignored(&patterns, "out", true));
+    }
+
+    #[test]
+    fn fuzzy_score_needs_every_letter_in_order() {
+        assert!(fuzzy_score("mrs", "src/main.rs").is_some());
+        assert!(fuzzy_score("srm", "main.rs").is_none());
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("MAIN", "src/main.rs").is_some());
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_tighter_matches() {
+        let score = |candidate| fuzzy_score("main", candidate).unwrap();
+        // consecutive beats spread out
+        assert!(score("main.rs") > score("m_a_i_n.rs"));
+        // in the file name beats in a directory
+        assert!(score("src/main.rs") > score("main/lib.rs"));
+        // starting a word beats the middle of one
+        assert!(score("src/main.rs") > score("src/domain.rs"));
+        assert!(fuzzy_score("fb", "foo_bar").unwrap() > fuzzy_score("fb", "fabric").unwrap());
+    }
+}
@@ -0,0 +1,354 @@
+use std::path::Path;
+
+use bevy::{prelude::*, transform::TransformSystem, utils::HashSet, window::RequestRedraw};
+use serde::{Deserialize, Serialize};
+
+use crate::cmdline::StatusMessage;
+use crate::indent::{indent_openers, IndentSettings};
+use crate::keymap::Action;
+use crate::marks::Mark;
+use crate::settings::Settings;
+use crate::structure::StructureNode;
+use crate::text_components::{character_str, line_characters, Background, Character, Document, DocumentPath, Line};
+use crate::{AppState, CurrentZipper, JumpToChar, ZipperFocus, ZipperType};
+
+pub struct FoldPlugin;
+
+impl Plugin for FoldPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (open_around_focus, fold_commands, hide_folded).chain())
+            .add_systems(PostUpdate, label_folds.after(TransformSystem::TransformPropagate));
+    }
+}
+
+/// How `zc` and friends find the region around a line, `auto` picking one by file extension
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FoldMethod {
+    #[default]
+    Auto,
+    /// A line and the more indented ones after it
+    Indent,
+    /// A line opening a bracket, through the line closing it
+    Brackets,
+    /// A Markdown heading, up to the next one of the same level or above
+    Headings,
+}
+
+impl FoldMethod {
+    fn resolve(self, path: &Path) -> FoldMethod {
+        if self != FoldMethod::Auto { return self }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md" | "markdown") => FoldMethod::Headings,
+            _ if indent_openers(path).contains(&"{") => FoldMethod::Brackets,
+            _ => FoldMethod::Indent,
+        }
+    }
+
+    /// The last line of the region `lines[start]` heads, when there's more to it than that line
+    fn region_end(self, lines: &[String], start: usize, tabstop: usize) -> Option<usize> {
+        let end = match self {
+            FoldMethod::Auto | FoldMethod::Indent => {
+                let indent_of = |line: &str| (!line.trim().is_empty()).then(|| line
+                    .chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .map(|c| if c == '\t' { tabstop } else { 1 })
+                    .sum::<usize>());
+                let base = indent_of(&lines[start])?;
+                let mut end = start;
+                for (i, line) in lines.iter().enumerate().skip(start + 1) {
+                    match indent_of(line) {
+                        // blank lines only count once something more indented follows
+                        None => continue,
+                        Some(indent) if indent > base => end = i,
+                        Some(_) => break,
+                    }
+                }
+                end
+            },
+            FoldMethod::Brackets => {
+                let mut depth = 0_i32;
+                let mut end = None;
+                for (i, line) in lines.iter().enumerate().skip(start) {
+                    let mut closed = false;
+                    for c in line.chars() {
+                        match c {
+                            '(' | '[' | '{' => depth += 1,
+                            ')' | ']' | '}' => depth -= 1,
+                            _ => continue,
+                        }
+                        if i == start {
+                            // `} else {` opens a region even though it closes one first
+                            depth = depth.max(0);
+                        } else if depth <= 0 {
+                            closed = true;
+                        }
+                    }
+                    if i == start && depth == 0 { return None }
+                    if closed {
+                        // a closing line that opens the next region, like `} else {`, stays out
+                        end = Some(if depth > 0 { i - 1 } else { i });
+                        break
+                    }
+                }
+                end?
+            },
+            FoldMethod::Headings => {
+                let level = |line: &str| {
+                    let hashes = line.len() - line.trim_start_matches('#').len();
+                    let rest = &line[hashes..];
+                    (hashes > 0 && (rest.is_empty() || rest.starts_with(' '))).then_some(hashes)
+                };
+                let base = level(&lines[start])?;
+                let next = lines
+                    .iter()
+                    .enumerate()
+                    .skip(start + 1)
+                    .find(|(_, line)| level(line).is_some_and(|level| level <= base))
+                    .map_or(lines.len(), |(i, _)| i);
+                let mut end = next - 1;
+                while end > start && lines[end].trim().is_empty() {
+                    end -= 1;
+                }
+                end
+            },
+        };
+        (end > start).then_some(end)
+    }
+}
+
+/// On the first line of a closed fold, the lines hidden under it
+#[derive(Component)]
+pub struct Fold(Vec<Entity>);
+
+/// A line hidden inside a closed fold
+#[derive(Component)]
+pub struct FoldedAway;
+
+/// `+-- 12 lines`, after the first line of a closed fold
+#[derive(Component)]
+struct FoldLabel;
+
+/// The line the zipper is on, or where the structure node it's on starts
+fn focused_line(
+    zipper: (&ZipperType, &ZipperFocus),
+    parents_q: &Query<&Parent>,
+    children_q: &Query<&Children>,
+    node_q: &Query<&StructureNode>,
+) -> Option<Entity> {
+    if let Ok(node) = node_q.get(**zipper.1) {
+        let span_id = parents_q.get(*node.chars.first()?).ok()?;
+        return parents_q.get(**span_id).ok().map(|line_id| **line_id)
+    }
+    Mark::focused(zipper, parents_q, children_q).map(|mark| mark.line_id)
+}
+
+/// Whether the zipper should pass over `focus`, which a fold hides
+pub fn folded_away(
+    focus: Entity,
+    folded_q: &Query<(), With<FoldedAway>>,
+    node_q: &Query<&StructureNode>,
+    parents_q: &Query<&Parent>,
+) -> bool {
+    let probe = node_q
+        .get(focus)
+        .ok()
+        .and_then(|node| node.chars.first().copied())
+        .unwrap_or(focus);
+    folded_q.contains(probe) || parents_q.iter_ancestors(probe).any(|ancestor| folded_q.contains(ancestor))
+}
+
+/// Opens the folds the cursor ended up in, and the one it starts editing on
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn open_around_focus(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    moved_q: Query<(), (With<CurrentZipper>, Or<(Added<CurrentZipper>, Changed<ZipperFocus>)>)>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    node_q: Query<&StructureNode>,
+    fold_q: Query<(Entity, &Fold)>,
+) {
+    if moved_q.is_empty() && !state.is_changed() { return }
+    let Ok(zipper) = curr_zipp_q.get_single() else { return };
+    let Some(line_id) = focused_line(zipper, &parents_q, &children_q, &node_q) else { return };
+    let editing = *state.get() == AppState::Insert;
+    for (header_id, Fold(body)) in fold_q.iter() {
+        if body.contains(&line_id) || (editing && header_id == line_id) {
+            commands.entity(header_id).remove::<Fold>();
+        }
+    }
+}
+
+/// `za`, `zc`, `zo`, `zR` and `zM`
+#[allow(clippy::too_many_arguments)]
+fn fold_commands(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    indent: Res<IndentSettings>,
+    mut status: ResMut<StatusMessage>,
+    mut action_evr: EventReader<Action>,
+    mut jump_evw: EventWriter<JumpToChar>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    node_q: Query<&StructureNode>,
+    doc_q: Query<(&Children, &DocumentPath), With<Document>>,
+    text_q: Query<&Text, With<Character>>,
+    fold_q: Query<(), With<Fold>>,
+) {
+    for action in action_evr.read() {
+        if !matches!(action,
+            Action::ToggleFold | Action::CloseFold | Action::OpenFold | Action::OpenAllFolds | Action::CloseAllFolds
+        ) { continue }
+        let Ok(zipper) = curr_zipp_q.get_single() else { continue };
+        let Some(line_id) = focused_line(zipper, &parents_q, &children_q, &node_q) else { continue };
+        let Ok((lines, doc_path)) = parents_q.get(line_id).and_then(|doc_id| doc_q.get(**doc_id)) else { continue };
+        let Some(row) = lines.iter().position(|id| *id == line_id) else { continue };
+        let closed = fold_q.contains(line_id);
+
+        let texts = lines
+            .iter()
+            .map(|id| line_characters(*id, &children_q)
+                .iter()
+                .filter_map(|char_id| text_q.get(*char_id).ok())
+                .map(character_str)
+                .collect::<String>())
+            .collect::<Vec<_>>();
+        let method = settings.foldmethod.resolve(doc_path);
+        let region_end = |start: usize| method.region_end(&texts, start, indent.tabstop);
+        let mut close = vec![];
+        // the header of the outermost fold closed over the cursor
+        let mut covering = None;
+        match action {
+            Action::OpenFold | Action::ToggleFold if closed => { commands.entity(line_id).remove::<Fold>(); },
+            Action::OpenFold => **status = Some("No fold found".into()),
+            Action::ToggleFold | Action::CloseFold => {
+                // the innermost region around the cursor that's still open
+                let region = (0..=row)
+                    .rev()
+                    .filter(|start| !fold_q.contains(lines[*start]))
+                    .find_map(|start| region_end(start).filter(|end| *end >= row).map(|end| (start, end)));
+                match region {
+                    Some((start, end)) => {
+                        close.push((start, end));
+                        covering = Some(start);
+                    },
+                    None => **status = Some("No fold found".into()),
+                }
+            },
+            Action::OpenAllFolds => {
+                for id in lines.iter().filter(|id| fold_q.contains(**id)) {
+                    commands.entity(*id).remove::<Fold>();
+                }
+            },
+            Action::CloseAllFolds => {
+                for start in 0..lines.len() {
+                    let Some(end) = region_end(start) else { continue };
+                    close.push((start, end));
+                    if covering.is_none() && start < row && row <= end {
+                        covering = Some(start);
+                    }
+                }
+            },
+            _ => (),
+        }
+        for (start, end) in close {
+            commands.entity(lines[start]).insert(Fold(lines[start + 1..=end].to_vec()));
+        }
+
+        let header = covering.filter(|start| *start != row).map(|start| lines[start]);
+        if let Some(char_id) = header.and_then(|id| line_characters(id, &children_q).first().copied()) {
+            jump_evw.send(JumpToChar(char_id));
+        }
+    }
+}
+
+/// Hides the lines of closed folds and shows those of opened ones
+fn hide_folded(
+    mut commands: Commands,
+    mut removed: RemovedComponents<Fold>,
+    changed_q: Query<(), Changed<Fold>>,
+    fold_q: Query<&Fold>,
+    mut lines_q: Query<(Entity, &mut Style, Has<FoldedAway>), With<Line>>,
+) {
+    if removed.read().count() == 0 && changed_q.is_empty() { return }
+    let hidden = fold_q
+        .iter()
+        .flat_map(|Fold(body)| body.iter().copied())
+        .collect::<HashSet<_>>();
+    for (line_id, mut style, folded) in lines_q.iter_mut() {
+        let hide = hidden.contains(&line_id);
+        if hide == folded { continue }
+        match hide {
+            true => {
+                style.display = Display::None;
+                commands.entity(line_id).insert(FoldedAway);
+            },
+            false => {
+                style.display = Display::Flex;
+                commands.entity(line_id).remove::<FoldedAway>();
+            },
+        }
+    }
+}
+
+/// Puts a `+-- 12 lines` label after the first line of every closed fold
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn label_folds(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut removed: RemovedComponents<Fold>,
+    moved_q: Query<(), (With<Line>, Or<(Changed<GlobalTransform>, Changed<Fold>)>)>,
+    // buffers are hidden and shown again by moving them out of and into the window
+    switched_q: Query<(), (With<Document>, Changed<Parent>)>,
+    fold_q: Query<(Entity, &Fold, &Parent), Without<FoldedAway>>,
+    background_q: Query<(), With<Background>>,
+    lines_q: Query<(), With<Line>>,
+    children_q: Query<&Children>,
+    nodes_q: Query<(&Node, &GlobalTransform)>,
+    mut label_q: Query<(Entity, &mut Text, &mut Style), With<FoldLabel>>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+) {
+    if removed.read().count() == 0 && moved_q.is_empty() && switched_q.is_empty() && !settings.is_changed() { return }
+    let font = match settings.font.as_str() {
+        "" => Handle::default(),
+        path => asset_server.load(path.to_string()),
+    };
+    let text_style = TextStyle { font, font_size: settings.font_size, color: settings.foreground.with_a(0.5) };
+
+    let mut labels = label_q.iter_mut();
+    for (header_id, Fold(body), doc_id) in fold_q.iter() {
+        if background_q.contains(doc_id.get()) { continue }
+        let Some(last) = line_characters(header_id, &children_q).last().copied() else { continue };
+        let Ok((node, transform)) = nodes_q.get(last) else { continue };
+        let glyph = Rect::from_center_size(transform.translation().truncate(), node.size());
+        let count = body.iter().filter(|id| lines_q.contains(**id)).count();
+        let text = Text::from_section(
+            format!("+-- {count} {}", if count == 1 { "line" } else { "lines" }),
+            text_style.clone(),
+        );
+        let style = Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(glyph.max.x + settings.font_size * 0.5),
+            top: Val::Px(glyph.min.y),
+            ..Default::default()
+        };
+        match labels.next() {
+            Some((_, mut label_text, mut label_style)) => {
+                *label_text = text;
+                *label_style = style;
+            },
+            None => {
+                commands.spawn((FoldLabel, TextBundle { text, style, z_index: ZIndex::Global(4), ..Default::default() }));
+            },
+        }
+    }
+    for (label_id, ..) in labels {
+        commands.entity(label_id).despawn_recursive();
+    }
+    // laid out on the next frame, which a reactive app has to be asked for
+    redraw_evw.send(RequestRedraw);
+}
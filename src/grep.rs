@@ -0,0 +1,273 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    window::RequestRedraw
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::cmdline::StatusMessage;
+use crate::finder::{project_root, walk_project};
+use crate::settings::Settings;
+use crate::text_components::{OpenFile, WorkingFilePath};
+
+/// Enough to step through, without a typo like `:grep e` filling memory
+const MAX_MATCHES: usize = 10_000;
+const LISTED: usize = 10;
+const SNIPPET_COLUMNS: usize = 120;
+
+pub struct GrepPlugin;
+
+impl Plugin for GrepPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(Update, (grep_commands, collect_matches, show_matches).chain())
+            .init_resource::<Grep>()
+            .add_event::<GrepCommand>();
+    }
+}
+
+/// `:grep pattern`, and stepping through what it found with `:cn`, `:cp` and `:cc [n]`
+#[derive(Event)]
+pub enum GrepCommand {
+    Search(String),
+    Next,
+    Previous,
+    /// The numbered match, from 1, or the current one again
+    Go(Option<usize>),
+    Show(bool),
+}
+
+struct GrepMatch {
+    /// Relative to the project root, with `/` between directories
+    file: String,
+    line: usize,
+    /// In characters, both counted from 0
+    column: usize,
+    snippet: String,
+}
+
+#[derive(Resource, Default)]
+struct Grep {
+    root: PathBuf,
+    pattern: String,
+    matches: Vec<GrepMatch>,
+    current: Option<usize>,
+    searching: Option<Task<Vec<GrepMatch>>>,
+    shown: bool,
+}
+
+impl Grep {
+    fn go_to(&mut self, index: usize, open_evw: &mut EventWriter<OpenFile>, status: &mut StatusMessage) {
+        let Some(found) = self.matches.get(index) else { return };
+        open_evw.send(OpenFile {
+            path: self.root.join(&found.file),
+            position: Some((found.line, found.column)),
+            record_jump: true,
+        });
+        **status = Some(format!("({} of {}): {}", index + 1, self.matches.len(), found.snippet));
+        self.current = Some(index);
+    }
+}
+
+/// Every line of the project containing `pattern`, ignoring case unless it has capitals
+fn search_project(root: &Path, pattern: &str) -> Vec<GrepMatch> {
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let pattern = match ignore_case {
+        true => pattern.to_lowercase(),
+        false => pattern.to_string(),
+    };
+    let mut matches = vec![];
+    for file in walk_project(root) {
+        let Ok(bytes) = fs::read(root.join(&file)) else { continue };
+        if bytes.contains(&0) { continue }
+        for (line, text) in String::from_utf8_lossy(&bytes).lines().enumerate() {
+            let start = match ignore_case {
+                true => find_ignoring_case(text, &pattern),
+                false => text.find(&pattern),
+            };
+            let Some(start) = start else { continue };
+            matches.push(GrepMatch {
+                file: file.clone(),
+                line,
+                column: text[..start].graphemes(true).count(),
+                snippet: text.trim().replace('\t', " ").chars().take(SNIPPET_COLUMNS).collect(),
+            });
+            if matches.len() >= MAX_MATCHES { return matches }
+        }
+    }
+    matches
+}
+
+/// Where lowercase `pattern` starts in `text`, as a byte offset into `text`. Lowercasing
+/// can change the length of a character, so the offset is taken back to the original
+fn find_ignoring_case(text: &str, pattern: &str) -> Option<usize> {
+    let mut lowered = String::with_capacity(text.len());
+    // the offset in `text` of each byte of `lowered`
+    let mut origins = Vec::with_capacity(text.len());
+    for (at, ch) in text.char_indices() {
+        lowered.extend(ch.to_lowercase());
+        origins.resize(lowered.len(), at);
+    }
+    lowered.find(pattern).map(|start| origins[start])
+}
+
+#[derive(Component)]
+struct GrepPanel;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        GrepPanel,
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(0.),
+                right: Val::Px(0.),
+                padding: UiRect::all(Val::Px(4.)),
+                overflow: Overflow::clip(),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.12, 0.12, 0.12)),
+            z_index: ZIndex::Global(8),
+            ..Default::default()
+        },
+    ));
+}
+
+fn grep_commands(
+    mut grep_evr: EventReader<GrepCommand>,
+    mut grep: ResMut<Grep>,
+    mut status: ResMut<StatusMessage>,
+    mut open_evw: EventWriter<OpenFile>,
+    file_path: Res<WorkingFilePath>,
+) {
+    for command in grep_evr.read() {
+        let step = match command {
+            GrepCommand::Search(pattern) => {
+                if pattern.is_empty() {
+                    **status = Some("Argument required".into());
+                    continue
+                }
+                let root = project_root(&file_path);
+                let search = {
+                    let (root, pattern) = (root.clone(), pattern.clone());
+                    AsyncComputeTaskPool::get().spawn(async move { search_project(&root, &pattern) })
+                };
+                // replacing an unfinished search drops, and so cancels, it
+                *grep = Grep {
+                    root,
+                    pattern: pattern.clone(),
+                    searching: Some(search),
+                    shown: true,
+                    ..Default::default()
+                };
+                **status = Some(format!("Searching for {pattern}..."));
+                continue
+            },
+            GrepCommand::Show(shown) => {
+                grep.shown = *shown;
+                continue
+            },
+            GrepCommand::Next => grep.current.map_or(0, |current| current + 1),
+            GrepCommand::Previous => match grep.current {
+                Some(current) if current > 0 => current - 1,
+                _ if grep.matches.is_empty() => 0,
+                _ => {
+                    **status = Some("Already at the first match".into());
+                    continue
+                },
+            },
+            GrepCommand::Go(number) => number
+                .map(|number| number.max(1) - 1)
+                .or(grep.current)
+                .unwrap_or(0)
+                .min(grep.matches.len().saturating_sub(1)),
+        };
+        if grep.matches.is_empty() {
+            **status = Some("No grep matches".into());
+        } else if step >= grep.matches.len() {
+            **status = Some("Already at the last match".into());
+        } else {
+            grep.go_to(step, &mut open_evw, &mut status);
+        }
+    }
+}
+
+/// Takes the matches once the search is done, and jumps to the first
+fn collect_matches(
+    mut grep: ResMut<Grep>,
+    mut status: ResMut<StatusMessage>,
+    mut open_evw: EventWriter<OpenFile>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+) {
+    let Some(search) = grep.searching.as_mut() else { return };
+    let Some(matches) = block_on(future::poll_once(search)) else {
+        // frames only come with input otherwise
+        redraw_evw.send(RequestRedraw);
+        return
+    };
+    grep.searching = None;
+    grep.matches = matches;
+    match grep.matches.is_empty() {
+        true => **status = Some(format!("No matches for {}", grep.pattern)),
+        false => grep.go_to(0, &mut open_evw, &mut status),
+    }
+}
+
+fn show_matches(
+    grep: Res<Grep>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut panel_q: Query<(&mut Style, &mut Text), With<GrepPanel>>,
+) {
+    if !grep.is_changed() && !settings.is_changed() { return }
+    let (mut panel_style, mut text) = panel_q.single_mut();
+    if !grep.shown {
+        panel_style.display = Display::None;
+        return
+    }
+    panel_style.display = Display::Flex;
+    // right above the command line
+    panel_style.bottom = Val::Px(settings.font_size * 1.2 + 8.);
+
+    let font = match settings.font.as_str() {
+        "" => Handle::default(),
+        path => asset_server.load(path.to_string()),
+    };
+    let style = |alpha: f32| TextStyle {
+        font: font.clone(),
+        font_size: settings.font_size,
+        color: settings.foreground.with_a(alpha),
+    };
+    let header = match grep.searching {
+        Some(_) => format!("grep {}: searching...", grep.pattern),
+        None => format!("grep {}: {} matches", grep.pattern, grep.matches.len()),
+    };
+    let mut sections = vec![TextSection::new(header, style(0.5))];
+    // keeps the current match in the middle of the list
+    let first = grep.current.unwrap_or(0).saturating_sub(LISTED / 2);
+    for (i, found) in grep.matches.iter().enumerate().skip(first).take(LISTED) {
+        let (marker, alpha) = if Some(i) == grep.current { ("> ", 1.) } else { ("  ", 0.7) };
+        let entry = format!("\n{marker}{}:{}: {}", found.file, found.line + 1, found.snippet);
+        sections.push(TextSection::new(entry, style(alpha)));
+    }
+    *text = Text::from_sections(sections);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive_offsets_point_into_the_original() {
+        assert_eq!(find_ignoring_case("Hello World", "world"), Some(6));
+        // `İ` lowercases to two characters
+        let text = "İİ needle";
+        let start = find_ignoring_case(text, "needle").unwrap();
+        assert_eq!(&text[start..], "needle");
+        assert_eq!(text[..start].graphemes(true).count(), 3);
+        assert_eq!(find_ignoring_case("abc", "x"), None);
+    }
+}
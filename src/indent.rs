@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::text_components::{
+    character_str, line_characters, mark_modified, remove_characters, spawn_placeholder, CharacterBundle,
+    DisplayWidth, Indent, Line, Modified, Span, SpanBundle
+};
+use crate::{CurrentZipper, Refocus, ZipperFocus, ZipperType};
+
+pub struct IndentPlugin;
+
+impl Plugin for IndentPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (
+                shift_line,
+                (mark_tabs, layout_tabs).chain(),
+            ))
+            .init_resource::<IndentSettings>()
+            .add_event::<ShiftLine>();
+    }
+}
+
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct IndentSettings {
+    /// Columns between tab stops when displaying a tab
+    pub tabstop: usize,
+    /// Insert spaces instead of tabs
+    pub expandtab: bool,
+    /// Columns added or removed by `>>` and `<<`
+    pub shiftwidth: usize,
+    /// Columns Tab and Backspace move over in Insert mode, 0 follows `tabstop`
+    pub softtabstop: usize,
+}
+
+impl Default for IndentSettings {
+    fn default() -> Self {
+        Self {
+            tabstop: 4,
+            expandtab: true,
+            shiftwidth: 4,
+            softtabstop: 0,
+        }
+    }
+}
+
+impl IndentSettings {
+    pub fn soft_step(&self) -> usize {
+        match self.softtabstop {
+            0 => self.tabstop.max(1),
+            step => step,
+        }
+    }
+
+    /// Whitespace filling `columns`, using tabs where `expandtab` is off
+    pub fn whitespace(&self, columns: usize) -> String {
+        if self.expandtab {
+            " ".repeat(columns)
+        } else {
+            let tabstop = self.tabstop.max(1);
+            "\t".repeat(columns / tabstop) + &" ".repeat(columns % tabstop)
+        }
+    }
+}
+
+/// Characters that open a new indentation level at the end of a line
+pub fn indent_openers(path: &Path) -> &'static [&'static str] {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("py") => &[":", "(", "[", "{"],
+        Some(
+            "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "go" | "java" | "js" | "jsx" | "ts"
+            | "tsx" | "json" | "css" | "kt" | "swift" | "zig"
+        ) => &["{", "(", "["],
+        _ => &[],
+    }
+}
+
+/// Characters that close an indentation level when they start a line
+pub const INDENT_CLOSERS: [&str; 3] = ["}", ")", "]"];
+
+/// Columns of indentation for a line split off after `last_before`, starting with `first_after`
+pub fn auto_indent(
+    indent: &IndentSettings,
+    path: &Path,
+    base: usize,
+    last_before: Option<&str>,
+    first_after: Option<&str>,
+) -> usize {
+    let openers = indent_openers(path);
+    let mut columns = base;
+    if last_before.is_some_and(|ch| openers.contains(&ch)) {
+        columns += indent.shiftwidth;
+    }
+    if !openers.is_empty() && first_after.is_some_and(|ch| INDENT_CLOSERS.contains(&ch)) {
+        columns = columns.saturating_sub(indent.shiftwidth);
+    }
+    columns
+}
+
+/// Replaces the `leading` whitespace of a line with a fresh `Indent` span
+pub fn set_indent(
+    commands: &mut Commands,
+    line_id: Entity,
+    leading: &[Entity],
+    whitespace: &str,
+    parents_q: &Query<&Parent>,
+    children_q: &Query<&Children>,
+) -> Vec<Entity> {
+    remove_characters(commands, leading, parents_q, children_q);
+    let new_chars = whitespace
+        .chars()
+        .map(|ch| commands.spawn(CharacterBundle::new(&ch.to_string())).id())
+        .collect::<Vec<_>>();
+    if !new_chars.is_empty() {
+        let span_id = commands
+            .spawn((Indent, SpanBundle::default()))
+            .push_children(&new_chars)
+            .id();
+        commands.entity(line_id).insert_children(0, &[span_id]);
+    }
+    new_chars
+}
+
+/// A `Character` holding `\t`, padded with spaces up to the next tab stop
+#[derive(Component, Reflect)]
+pub struct Tab;
+
+fn mark_tabs(
+    mut commands: Commands,
+    new_chars_q: Query<(Entity, &Text), Added<DisplayWidth>>,
+) {
+    for (char_id, text) in new_chars_q.iter() {
+        if character_str(text) == "\t" {
+            commands.entity(char_id).insert(Tab);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn layout_tabs(
+    indent: Res<IndentSettings>,
+    lines_q: Query<Entity, With<Line>>,
+    changed_lines_q: Query<Entity, (With<Line>, Changed<Children>)>,
+    changed_spans_q: Query<&Parent, (With<Span>, Changed<Children>)>,
+    new_tabs_q: Query<&Parent, Added<Tab>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    mut chars_q: Query<(&mut DisplayWidth, Option<&mut Text>, Has<Tab>)>,
+) {
+    let lines: Vec<Entity> = if indent.is_changed() {
+        lines_q.iter().collect()
+    } else {
+        changed_lines_q
+            .iter()
+            .chain(changed_spans_q.iter().map(|line_id| **line_id))
+            .chain(new_tabs_q.iter().filter_map(|span_id| parents_q.get(**span_id).ok()).map(|line_id| **line_id))
+            .collect()
+    };
+    let tabstop = indent.tabstop.max(1);
+    for line_id in lines {
+        let mut col = 0;
+        for char_id in line_characters(line_id, &children_q) {
+            let Ok((mut width, text, is_tab)) = chars_q.get_mut(char_id) else { continue };
+            if is_tab {
+                let tab_width = tabstop - col % tabstop;
+                if **width != tab_width {
+                    **width = tab_width;
+                }
+                // the `\t` itself takes no room, so a second section stands in for it
+                if let Some(mut text) = text {
+                    let spaces = " ".repeat(tab_width);
+                    match text.sections.get_mut(1) {
+                        Some(padding) if padding.value == spaces => (),
+                        Some(padding) => padding.value = spaces,
+                        None => {
+                            let style = text.sections[0].style.clone();
+                            text.sections.push(TextSection::new(spaces, style));
+                        },
+                    }
+                }
+            }
+            col += **width;
+        }
+    }
+}
+
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShiftLine {
+    Indent,
+    Dedent,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shift_line(
+    mut commands: Commands,
+    mut shift_evr: EventReader<ShiftLine>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut modified_q: Query<&mut Modified>,
+    indent: Res<IndentSettings>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    chars_q: Query<(&Text, &DisplayWidth, Has<Tab>)>,
+) {
+    // shifts sent together, like a repeated `>>`, add up
+    let levels = shift_evr
+        .read()
+        .map(|shift| match shift {
+            ShiftLine::Indent => 1,
+            ShiftLine::Dedent => -1,
+        })
+        .collect::<Vec<isize>>();
+    if levels.is_empty() { return }
+    let levels = levels.iter().sum::<isize>();
+    let Ok((ZipperType::Character, focus)) = curr_zipp_q.get_single() else { return };
+    let Some(line_id) = parents_q
+        .get(**focus)
+        .and_then(|span_id| parents_q.get(**span_id))
+        .ok()
+        .map(|line_id| **line_id)
+    else { return };
+
+    let line_chars = line_characters(line_id, &children_q);
+    let leading = line_chars
+        .iter()
+        .take_while(|char_id| chars_q
+            .get(**char_id)
+            .is_ok_and(|(text, _, is_tab)| is_tab || character_str(text) == " "))
+        .copied()
+        .collect::<Vec<_>>();
+    let current = leading
+        .iter()
+        .filter_map(|char_id| chars_q.get(*char_id).ok())
+        .map(|(_, width, _)| **width)
+        .sum::<usize>();
+    let step = levels.unsigned_abs() * indent.shiftwidth;
+    let columns = match levels {
+        1.. => current + step,
+        _ => current.saturating_sub(step),
+    };
+    if columns == current { return }
+
+    let mut new_chars = set_indent(
+        &mut commands,
+        line_id,
+        &leading,
+        &indent.whitespace(columns),
+        &parents_q,
+        &children_q,
+    );
+    if new_chars.is_empty() && leading.len() == line_chars.len() {
+        new_chars.push(spawn_placeholder(&mut commands, line_id));
+    }
+    mark_modified(line_id, &parents_q, &mut modified_q);
+
+    let new_focus = if leading.contains(&**focus) {
+        line_chars.get(leading.len()).or(new_chars.first()).copied()
+    } else {
+        Some(**focus)
+    };
+    if let Some(new_focus) = new_focus {
+        refocus_evw.send(Refocus(new_focus));
+    }
+}
@@ -0,0 +1,632 @@
+use std::{collections::{HashMap as StdHashMap, VecDeque}, fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use bevy::{
+    input::{keyboard::{Key, KeyboardInput}, ButtonState}, prelude::*, utils::HashMap,
+    window::RequestRedraw
+};
+use serde::{Deserialize, Serialize};
+
+use crate::macros::{register_name, Macros};
+use crate::repeat::RepeatChange;
+use crate::{cmdline::StatusMessage, prompt::prompt_inactive, AppState};
+
+/// Replays past this many queued keys are taken to be a macro calling itself
+const MAX_QUEUED_KEYS: usize = 100_000;
+
+pub struct KeymapPlugin;
+
+impl Plugin for KeymapPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, load_keymap)
+            .add_systems(Update, dispatch_keys.run_if(prompt_inactive))
+            .init_resource::<Keymap>()
+            .init_resource::<PendingKeys>()
+            .add_event::<Action>()
+            .add_event::<KeyedAction>()
+            .add_event::<TypedText>();
+    }
+}
+
+/// Everything a key sequence can be bound to, named as in the keymap file
+#[derive(Event, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Unbinds the sequence
+    Nop,
+    NormalMode,
+    InsertMode,
+    TravelMode,
+    CommandMode,
+    /// Opens the fuzzy file finder
+    FindFile,
+    /// Shows the explorer sidebar and travels into it, or hides it from inside
+    ToggleExplorer,
+    /// Opens the command palette
+    CommandPalette,
+    Save,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    IndentLine,
+    DedentLine,
+    JumpToMatch,
+    Newline,
+    Backspace,
+    DeleteForward,
+    Tab,
+    ZipperLeft,
+    ZipperRight,
+    ZipperChild,
+    ZipperParent,
+    /// Deletes everything the focus covers, in Travel mode
+    DeleteFocus,
+    /// Takes the next key as the register to record into, or stops recording
+    RecordMacro,
+    /// Takes the next key as the register to replay, `@` for the last one
+    PlayMacro,
+    /// Applies the last change again
+    Repeat,
+    /// Takes the next key as the mark to set
+    SetMark,
+    /// Takes the next key as the mark to jump to
+    JumpToMark,
+    /// Takes the next key as the mark whose line to jump to
+    JumpToMarkLine,
+    /// Back through the jump list
+    JumpBack,
+    /// Forward through the jump list
+    JumpForward,
+    FirstLine,
+    LastLine,
+    /// `zz`, `zt` and `zb`: scroll the cursor line to the middle, top or bottom
+    CenterView,
+    ViewTop,
+    ViewBottom,
+    /// `za`, `zc` and `zo`: toggle, close or open the fold under the cursor
+    ToggleFold,
+    CloseFold,
+    OpenFold,
+    /// `zR` and `zM`
+    OpenAllFolds,
+    CloseAllFolds,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    /// Leaves the command line, the finder or the palette
+    Cancel,
+    /// Runs the command line or the picked action, or opens the picked file or explorer entry
+    Execute,
+    /// Moves down or up the finder's or the palette's list
+    SelectNext,
+    SelectPrevious,
+}
+
+impl Action {
+    /// Every action, in the order the command palette lists them
+    pub const ALL: &'static [Action] = {
+        use Action::*;
+        &[
+            Nop, NormalMode, InsertMode, TravelMode, CommandMode, FindFile, ToggleExplorer, CommandPalette, Save,
+            MoveLeft, MoveRight, MoveUp, MoveDown, FirstLine, LastLine, JumpToMatch,
+            IndentLine, DedentLine, Newline, Backspace, DeleteForward, Tab,
+            ZipperLeft, ZipperRight, ZipperChild, ZipperParent, DeleteFocus,
+            RecordMacro, PlayMacro, Repeat, SetMark, JumpToMark, JumpToMarkLine, JumpBack, JumpForward,
+            CenterView, ViewTop, ViewBottom, HalfPageDown, HalfPageUp, PageDown, PageUp,
+            ToggleFold, CloseFold, OpenFold, OpenAllFolds, CloseAllFolds,
+            Cancel, Execute, SelectNext, SelectPrevious,
+        ]
+    };
+
+    /// The name keymap files use
+    pub fn name(&self) -> String {
+        toml::Value::try_from(self)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_else(|| format!("{self:?}"))
+    }
+
+    /// What it does, as the command palette shows it
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Nop => "Do nothing",
+            Action::NormalMode => "Switch to Normal mode",
+            Action::InsertMode => "Switch to Insert mode",
+            Action::TravelMode => "Switch to Travel mode",
+            Action::CommandMode => "Open the command line",
+            Action::FindFile => "Find a file in the project and open it",
+            Action::ToggleExplorer => "Show the file explorer and travel into it, or hide it",
+            Action::CommandPalette => "List every action",
+            Action::Save => "Write the document to its file",
+            Action::MoveLeft => "Move the cursor left",
+            Action::MoveRight => "Move the cursor right",
+            Action::MoveUp => "Move the cursor up a line",
+            Action::MoveDown => "Move the cursor down a line",
+            Action::FirstLine => "Jump to the first line",
+            Action::LastLine => "Jump to the last line",
+            Action::JumpToMatch => "Jump to the matching bracket",
+            Action::IndentLine => "Indent the line",
+            Action::DedentLine => "Dedent the line",
+            Action::Newline => "Break the line at the cursor",
+            Action::Backspace => "Delete the character before the cursor",
+            Action::DeleteForward => "Delete the character under the cursor",
+            Action::Tab => "Insert a tab or the spaces standing in for one",
+            Action::ZipperLeft => "Travel to the previous sibling",
+            Action::ZipperRight => "Travel to the next sibling",
+            Action::ZipperChild => "Travel into the focus",
+            Action::ZipperParent => "Travel out to the parent",
+            Action::DeleteFocus => "Delete everything the focus covers",
+            Action::RecordMacro => "Record keys into a register, or stop recording",
+            Action::PlayMacro => "Replay the keys in a register",
+            Action::Repeat => "Apply the last change again",
+            Action::SetMark => "Set a mark at the cursor",
+            Action::JumpToMark => "Jump to a mark",
+            Action::JumpToMarkLine => "Jump to the line of a mark",
+            Action::JumpBack => "Go back through the jump list",
+            Action::JumpForward => "Go forward through the jump list",
+            Action::CenterView => "Scroll the cursor line to the middle",
+            Action::ViewTop => "Scroll the cursor line to the top",
+            Action::ViewBottom => "Scroll the cursor line to the bottom",
+            Action::HalfPageDown => "Scroll down half a page",
+            Action::HalfPageUp => "Scroll up half a page",
+            Action::PageDown => "Scroll down a page",
+            Action::PageUp => "Scroll up a page",
+            Action::ToggleFold => "Open or close the fold under the cursor",
+            Action::CloseFold => "Close the fold around the cursor",
+            Action::OpenFold => "Open the fold under the cursor",
+            Action::OpenAllFolds => "Open every fold",
+            Action::CloseAllFolds => "Close every fold",
+            Action::Cancel => "Leave the command line, finder or palette",
+            Action::Execute => "Run the command line, take the picked entry, or open an explorer entry",
+            Action::SelectNext => "Pick the next entry of a list",
+            Action::SelectPrevious => "Pick the previous entry of a list",
+        }
+    }
+
+    /// Whether the palette offers it. Actions needing a key after them, or
+    /// only meaningful inside an overlay, are left out
+    pub fn in_palette(&self) -> bool {
+        !self.takes_key() && !matches!(self,
+            Action::Nop | Action::CommandPalette | Action::Cancel | Action::Execute
+            | Action::SelectNext | Action::SelectPrevious
+        )
+    }
+
+    /// The key sequences bound to it in each mode
+    pub fn bindings(&self, keymap: &Keymap) -> Vec<(AppState, String)> {
+        let mut bound = vec![];
+        for state in [AppState::Normal, AppState::Insert, AppState::Travel, AppState::Command, AppState::Find, AppState::Palette] {
+            let Some(bindings) = keymap.modes.get(&state) else { continue };
+            let mut sequences = bindings
+                .iter()
+                .filter(|(_, action)| *action == self)
+                .map(|(keys, _)| keys.concat())
+                .collect::<Vec<_>>();
+            sequences.sort();
+            bound.extend(sequences.into_iter().map(|keys| (state, keys)));
+        }
+        bound
+    }
+
+    /// Actions that apply to the key typed after them, like `m{a-z}`
+    pub fn takes_key(&self) -> bool {
+        matches!(self,
+            Action::RecordMacro | Action::PlayMacro | Action::SetMark
+            | Action::JumpToMark | Action::JumpToMarkLine
+        )
+    }
+}
+
+/// An action along with the key typed after it
+#[derive(Event, Clone, Copy, Debug)]
+pub struct KeyedAction {
+    pub action: Action,
+    pub key: char,
+}
+
+/// Text typed in Insert mode that isn't part of a binding
+#[derive(Event)]
+pub struct TypedText(pub String);
+
+/// Key sequences bound to actions, per mode. Keys are single characters or
+/// named keys in angle brackets: `<Esc>`, `<CR>`, `<BS>`, `<Del>`, `<Tab>`, `<C-s>`, `<C-S-p>`
+#[derive(Resource, Clone, Debug)]
+pub struct Keymap {
+    pub leader: String,
+    pub modes: HashMap<AppState, HashMap<Vec<String>, Action>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        let mut keymap = Self { leader: "\\".into(), modes: HashMap::new() };
+        let defaults: [(AppState, &[(&str, Action)]); 6] = [
+            (AppState::Normal, &[
+                ("h", MoveLeft),
+                ("l", MoveRight),
+                ("j", MoveDown),
+                ("k", MoveUp),
+                (">>", IndentLine),
+                ("<<", DedentLine),
+                ("%", JumpToMatch),
+                (".", Repeat),
+                ("m", SetMark),
+                ("`", JumpToMark),
+                ("'", JumpToMarkLine),
+                ("<C-o>", JumpBack),
+                ("<C-i>", JumpForward),
+                ("gg", FirstLine),
+                ("G", LastLine),
+                ("zz", CenterView),
+                ("zt", ViewTop),
+                ("zb", ViewBottom),
+                ("za", ToggleFold),
+                ("zc", CloseFold),
+                ("zo", OpenFold),
+                ("zR", OpenAllFolds),
+                ("zM", CloseAllFolds),
+                ("<C-d>", HalfPageDown),
+                ("<C-u>", HalfPageUp),
+                ("<C-f>", PageDown),
+                ("<C-b>", PageUp),
+                ("q", RecordMacro),
+                ("@", PlayMacro),
+                ("i", InsertMode),
+                (":", CommandMode),
+                ("<C-p>", FindFile),
+                ("<C-e>", ToggleExplorer),
+                ("<C-S-p>", CommandPalette),
+                ("<C-t>", TravelMode),
+                ("<C-s>", Save),
+            ]),
+            (AppState::Insert, &[
+                ("<Esc>", NormalMode),
+                ("<CR>", Newline),
+                ("<BS>", Backspace),
+                ("<Del>", DeleteForward),
+                ("<Tab>", Tab),
+                ("<C-S-p>", CommandPalette),
+                ("<C-t>", TravelMode),
+                ("<C-s>", Save),
+            ]),
+            (AppState::Travel, &[
+                ("h", ZipperLeft),
+                ("a", ZipperLeft),
+                ("l", ZipperRight),
+                ("d", ZipperRight),
+                ("j", ZipperChild),
+                ("w", ZipperChild),
+                ("k", ZipperParent),
+                ("s", ZipperParent),
+                ("x", DeleteFocus),
+                ("za", ToggleFold),
+                ("zc", CloseFold),
+                ("zo", OpenFold),
+                ("zR", OpenAllFolds),
+                ("zM", CloseAllFolds),
+                ("q", RecordMacro),
+                ("@", PlayMacro),
+                ("m", SetMark),
+                ("`", JumpToMark),
+                ("'", JumpToMarkLine),
+                ("<C-o>", JumpBack),
+                ("<C-i>", JumpForward),
+                ("<Esc>", NormalMode),
+                ("i", InsertMode),
+                (":", CommandMode),
+                ("<C-p>", FindFile),
+                ("<C-e>", ToggleExplorer),
+                ("<C-S-p>", CommandPalette),
+                ("<CR>", Execute),
+                ("<C-s>", Save),
+            ]),
+            (AppState::Command, &[
+                ("<Esc>", Cancel),
+                ("<CR>", Execute),
+                ("<BS>", Backspace),
+            ]),
+            (AppState::Find, &[
+                ("<Esc>", Cancel),
+                ("<CR>", Execute),
+                ("<BS>", Backspace),
+                ("<Down>", SelectNext),
+                ("<C-n>", SelectNext),
+                ("<Up>", SelectPrevious),
+                ("<C-p>", SelectPrevious),
+            ]),
+            (AppState::Palette, &[
+                ("<Esc>", Cancel),
+                ("<CR>", Execute),
+                ("<BS>", Backspace),
+                ("<Down>", SelectNext),
+                ("<C-n>", SelectNext),
+                ("<Up>", SelectPrevious),
+                ("<C-p>", SelectPrevious),
+            ]),
+        ];
+        for (state, bindings) in defaults {
+            for (keys, action) in bindings {
+                keymap.bind(state, keys, *action).expect("default bindings parse");
+            }
+        }
+        keymap
+    }
+}
+
+/// `keymap.toml`: an optional `leader` and a table of `keys = "action"` per mode,
+/// layered over the defaults
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct KeymapFile {
+    leader: Option<String>,
+    normal: StdHashMap<String, Action>,
+    insert: StdHashMap<String, Action>,
+    travel: StdHashMap<String, Action>,
+    command: StdHashMap<String, Action>,
+    find: StdHashMap<String, Action>,
+    palette: StdHashMap<String, Action>,
+}
+
+impl Keymap {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("diy-ed").join("keymap.toml"))
+    }
+
+    pub fn load(path: &PathBuf) -> anyhow::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let file: KeymapFile = toml::from_str(&source)?;
+        let mut keymap = Self::default();
+        if let Some(leader) = file.leader {
+            match parse_keys(&leader, &keymap.leader)?.as_slice() {
+                [key] => keymap.leader = key.clone(),
+                _ => bail!("leader has to be a single key, not {leader:?}"),
+            }
+        }
+        for (state, bindings) in [
+            (AppState::Normal, file.normal),
+            (AppState::Insert, file.insert),
+            (AppState::Travel, file.travel),
+            (AppState::Command, file.command),
+            (AppState::Find, file.find),
+            (AppState::Palette, file.palette),
+        ] {
+            for (keys, action) in bindings {
+                keymap.bind(state, &keys, action).with_context(|| format!("binding {keys:?}"))?;
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn bind(&mut self, state: AppState, keys: &str, action: Action) -> anyhow::Result<()> {
+        let keys = parse_keys(keys, &self.leader)?;
+        let bindings = self.modes.entry(state).or_default();
+        match action {
+            Action::Nop => { bindings.remove(&keys); },
+            action => { bindings.insert(keys, action); },
+        }
+        Ok(())
+    }
+}
+
+/// Splits `<leader>w`, `>>` or `<C-s>` into the keys typed for them
+pub fn parse_keys(notation: &str, leader: &str) -> anyhow::Result<Vec<String>> {
+    let mut keys = vec![];
+    let mut rest = notation;
+    while let Some(ch) = rest.chars().next() {
+        // a lone `<` is just the character
+        let named = rest
+            .strip_prefix('<')
+            .and_then(|tail| tail.split_once('>'))
+            .filter(|(name, _)| !name.is_empty() && !name.contains('<'));
+        if let Some((name, tail)) = named {
+            keys.push(named_key(name, leader)?);
+            rest = tail;
+        } else {
+            keys.push(ch.to_string());
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    if keys.is_empty() { bail!("empty key sequence") }
+    Ok(keys)
+}
+
+fn named_key(name: &str, leader: &str) -> anyhow::Result<String> {
+    let key = match name.to_lowercase().as_str() {
+        "leader" => leader.to_string(),
+        "esc" => "<Esc>".into(),
+        "cr" | "enter" | "return" => "<CR>".into(),
+        "bs" | "backspace" => "<BS>".into(),
+        "del" | "delete" => "<Del>".into(),
+        "tab" => "<Tab>".into(),
+        "up" => "<Up>".into(),
+        "down" => "<Down>".into(),
+        "space" => " ".into(),
+        "lt" => "<".into(),
+        lower => match lower.strip_prefix("c-") {
+            Some(ch) if ch.chars().count() == 1 => format!("<C-{ch}>"),
+            Some(chord) => match chord.strip_prefix("s-") {
+                Some(ch) if ch.chars().count() == 1 => format!("<C-S-{ch}>"),
+                _ => bail!("unknown key <{name}>"),
+            },
+            _ => bail!("unknown key <{name}>"),
+        },
+    };
+    Ok(key)
+}
+
+/// Named keys and chords, as opposed to text
+fn is_named(key: &str) -> bool {
+    key.len() > 1 && key.starts_with('<')
+}
+
+fn key_name(key: &KeyboardInput, ctrl: bool, shift: bool) -> Option<String> {
+    match &key.logical_key {
+        Key::Escape => Some("<Esc>".into()),
+        Key::Enter => Some("<CR>".into()),
+        Key::Backspace => Some("<BS>".into()),
+        Key::Delete => Some("<Del>".into()),
+        Key::Tab => Some("<Tab>".into()),
+        Key::ArrowUp => Some("<Up>".into()),
+        Key::ArrowDown => Some("<Down>".into()),
+        Key::Character(ch) if ctrl && shift => Some(format!("<C-S-{}>", ch.to_lowercase())),
+        Key::Character(ch) if ctrl => Some(format!("<C-{}>", ch.to_lowercase())),
+        // text comes from here too, so it keeps its place among the named keys
+        Key::Character(ch) if !ch.chars().any(char::is_control) => Some(ch.to_string()),
+        Key::Space => Some(" ".into()),
+        _ => None,
+    }
+}
+
+fn load_keymap(mut keymap: ResMut<Keymap>, mut status: ResMut<StatusMessage>) {
+    let Some(path) = Keymap::path() else { return };
+    if !path.exists() { return }
+    match Keymap::load(&path) {
+        Ok(loaded) => *keymap = loaded,
+        Err(err) => **status = Some(format!("{}: {err:#}", path.display())),
+    }
+}
+
+/// Set while keys typed so far are the start of a longer binding, or an
+/// action waits for the key it applies to
+#[derive(Resource, Default, Deref, PartialEq)]
+pub struct PendingKeys(pub bool);
+
+/// A key waiting its turn, typed or replayed from a register
+struct QueuedKey {
+    key: String,
+    replayed: bool,
+}
+
+#[derive(Default)]
+pub struct KeyDispatch {
+    queue: VecDeque<QueuedKey>,
+    pending: Vec<String>,
+    count: Option<usize>,
+    /// An action waiting for the key it applies to, and its count
+    argument: Option<(Action, usize)>,
+}
+
+/// Turns keys into `Action`s for the current mode, holding on to them while
+/// they could still become a longer binding. Keys after an action wait for the
+/// next frame, so that replays see the mode and zipper that action left behind
+#[allow(clippy::too_many_arguments)]
+pub fn dispatch_keys(
+    state: Res<State<AppState>>,
+    keymap: Res<Keymap>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut macros: ResMut<Macros>,
+    mut status: ResMut<StatusMessage>,
+    mut pending_keys: ResMut<PendingKeys>,
+    mut dispatch: Local<KeyDispatch>,
+    mut keyb_input_evr: EventReader<KeyboardInput>,
+    mut action_evw: EventWriter<Action>,
+    mut text_evw: EventWriter<TypedText>,
+    mut keyed_evw: EventWriter<KeyedAction>,
+    mut repeat_evw: EventWriter<RepeatChange>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+) {
+    let dispatch = &mut *dispatch;
+    if state.is_changed() {
+        dispatch.pending.clear();
+        dispatch.count = None;
+        dispatch.argument = None;
+    }
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let typed = keyb_input_evr
+        .read()
+        .filter(|key| key.state == ButtonState::Pressed)
+        .filter_map(|key| key_name(key, ctrl, shift))
+        .collect::<Vec<_>>();
+    dispatch.queue.extend(typed.into_iter().map(|key| QueuedKey { key, replayed: false }));
+    let Some(bindings) = keymap.modes.get(state.get()) else { return };
+    let inserting = matches!(state.get(), AppState::Insert | AppState::Command | AppState::Find | AppState::Palette);
+
+    while let Some(QueuedKey { key, replayed }) = dispatch.queue.pop_front() {
+        if !replayed {
+            macros.record(&key);
+        }
+        if let Some((action, count)) = dispatch.argument.take() {
+            let Some(register) = register_name(&key) else { continue };
+            match action {
+                Action::RecordMacro => {
+                    macros.start_recording(register);
+                    **status = Some(format!("recording @{register}"));
+                },
+                Action::PlayMacro => {
+                    let Some(keys) = macros.play(register) else { continue };
+                    if dispatch.queue.len() + keys.len() * count > MAX_QUEUED_KEYS {
+                        **status = Some(format!("@{register} is too long to replay"));
+                        dispatch.queue.clear();
+                        break
+                    }
+                    // the replay goes before anything typed after it
+                    for _ in 0..count {
+                        for key in keys.iter().rev() {
+                            dispatch.queue.push_front(QueuedKey { key: key.clone(), replayed: true });
+                        }
+                    }
+                },
+                action => {
+                    keyed_evw.send(KeyedAction { action, key: register });
+                    break
+                },
+            }
+            continue
+        }
+
+        let is_digit = key.len() == 1 && key.chars().all(|ch| ch.is_ascii_digit());
+        if !inserting
+            && is_digit
+            && dispatch.pending.is_empty()
+            && (key != "0" || dispatch.count.is_some())
+            && !bindings.contains_key(&vec![key.clone()])
+        {
+            let digit = key.parse::<usize>().unwrap_or(0);
+            dispatch.count = Some(dispatch.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            continue
+        }
+
+        dispatch.pending.push(key);
+        let mut acted = false;
+        while !dispatch.pending.is_empty() {
+            if let Some(action) = bindings.get(&dispatch.pending).copied() {
+                let sequence = std::mem::take(&mut dispatch.pending);
+                let count = dispatch.count.take();
+                match action {
+                    Action::RecordMacro if macros.is_recording() => {
+                        macros.stop_recording(sequence.len());
+                        **status = None;
+                    },
+                    action if action.takes_key() => {
+                        dispatch.argument = Some((action, count.unwrap_or(1)));
+                    },
+                    Action::Repeat => {
+                        repeat_evw.send(RepeatChange(count.unwrap_or(1)));
+                        acted = true;
+                    },
+                    action => {
+                        action_evw.send(action);
+                        acted = true;
+                    },
+                }
+            } else if bindings.keys().any(|keys| keys.starts_with(&dispatch.pending)) {
+                break
+            } else {
+                // nothing starts like this, so the first key stands on its own
+                let first = dispatch.pending.remove(0);
+                dispatch.count = None;
+                if inserting && !is_named(&first) {
+                    text_evw.send(TypedText(first));
+                }
+            }
+        }
+        if acted { break }
+    }
+    pending_keys.set_if_neq(PendingKeys(!dispatch.pending.is_empty() || dispatch.argument.is_some()));
+    if !dispatch.queue.is_empty() {
+        redraw_evw.send(RequestRedraw);
+    }
+}
@@ -0,0 +1,59 @@
+use bevy::{prelude::*, utils::HashMap};
+
+pub struct MacroPlugin;
+
+impl Plugin for MacroPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.init_resource::<Macros>();
+    }
+}
+
+/// Keys recorded with `q{reg}`, replayed with `@{reg}`
+#[derive(Resource, Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<String>>,
+    recording: Option<(char, Vec<String>)>,
+    last_played: Option<char>,
+}
+
+impl Macros {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn start_recording(&mut self, register: char) {
+        self.recording = Some((register, vec![]));
+    }
+
+    pub fn record(&mut self, key: &str) {
+        if let Some((_, keys)) = self.recording.as_mut() {
+            keys.push(key.to_string());
+        }
+    }
+
+    /// Stores what was recorded, minus the last `trailing` keys that stopped it
+    pub fn stop_recording(&mut self, trailing: usize) {
+        let Some((register, mut keys)) = self.recording.take() else { return };
+        keys.truncate(keys.len().saturating_sub(trailing));
+        self.registers.insert(register, keys);
+    }
+
+    /// The keys in a register, `@` being the one played last
+    pub fn play(&mut self, register: char) -> Option<&[String]> {
+        let register = match register {
+            '@' => self.last_played?,
+            register => register,
+        };
+        self.last_played = Some(register);
+        self.registers.get(&register).map(Vec::as_slice)
+    }
+}
+
+/// The register a key names, any single character
+pub fn register_name(key: &str) -> Option<char> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(register), None) => Some(register),
+        _ => None,
+    }
+}
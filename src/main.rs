@@ -1,24 +1,85 @@
 use std::{cmp::min, collections::VecDeque, fs};
 
 use bevy::{
-    ecs::system::SystemState, input::{keyboard::KeyboardInput, ButtonState}, prelude::*, reflect::List, winit::WinitSettings
+    ecs::system::SystemState, prelude::*, window::RequestRedraw, winit::WinitSettings
 };
 use bevy_inspector_egui::quick::StateInspectorPlugin;
 use iyes_perf_ui::{PerfUiCompleteBundle, PerfUiPlugin};
 
+mod autopairs;
+mod brackets;
+mod breadcrumb;
+mod caret;
+mod cmdline;
+mod explorer;
+mod file_watch;
+mod finder;
+mod fold;
+mod grep;
+mod indent;
+mod keymap;
+mod macros;
+mod marks;
+mod mouse;
+mod palette;
+mod prompt;
+mod repeat;
+mod segment;
+mod settings;
+mod structure;
+mod swap;
 mod text_components;
+mod viewport;
+mod wrap;
 
-use text_components::{scroll, AppWindow, Character, Document, DocumentPlugin, Line, Scroll, Span, WorkingFilePath};
+use autopairs::{AutoPairs, AutoPairsPlugin};
+use brackets::{BracketPlugin, JumpToMatch};
+use breadcrumb::BreadcrumbPlugin;
+use caret::{shows_caret, CaretPlugin};
+use cmdline::{CommandLinePlugin, StatusMessage};
+use explorer::{explorer_children, Entry, EntryLabel, Expanded, Explorer, ExplorerPlugin};
+use file_watch::{DiskStamp, FileStamp, FileWatchPlugin};
+use finder::FinderPlugin;
+use fold::{folded_away, FoldPlugin, FoldedAway};
+use grep::GrepPlugin;
+use indent::{auto_indent, indent_openers, set_indent, IndentPlugin, IndentSettings, ShiftLine, INDENT_CLOSERS};
+use keymap::{dispatch_keys, Action, KeymapPlugin, TypedText};
+use macros::MacroPlugin;
+use marks::MarkPlugin;
+use mouse::MousePlugin;
+use palette::PalettePlugin;
+use prompt::{prompt_inactive, PromptPlugin};
+use repeat::RepeatPlugin;
+use segment::SegmentPlugin;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsPlugin};
+use structure::{
+    build_structure, structure_children, structure_path, Structure, StructureNode, StructurePlugin,
+    StructureRoot
+};
+use swap::SwapPlugin;
+use text_components::{
+    character_str, document_text, line_characters, mark_modified, remove_characters, spawn_placeholder,
+    AppWindow, Background, Character, CharacterBundle, DisplayWidth, Document, DocumentPath, DocumentPlugin,
+    Indent, Line, LineBundle, Modified, OpenFile, ReplaceContent, Span, SpanBundle, WorkingFilePath
+};
+use unicode_segmentation::UnicodeSegmentation;
+use viewport::ViewportPlugin;
+use wrap::WrapPlugin;
 
 #[derive(Component)]
 pub struct MainCamera;
 
-#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug, Reflect)]
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug, Reflect, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AppState {
     Normal,
     Insert,
     #[default]
     Travel,
+    Command,
+    Find,
+    Palette,
 }
 
 fn main() {
@@ -38,12 +99,43 @@ fn main() {
         .add_plugins(StateInspectorPlugin::<AppState>::default())
         .add_plugins(PerfUiPlugin)
         .add_plugins(DocumentPlugin)
+        .add_plugins(PromptPlugin)
+        .add_plugins(SwapPlugin)
+        .add_plugins(CommandLinePlugin)
+        .add_plugins(FileWatchPlugin)
+        .add_plugins(IndentPlugin)
+        .add_plugins(BracketPlugin)
+        .add_plugins(AutoPairsPlugin)
+        .add_plugins(KeymapPlugin)
+        .add_plugins(MacroPlugin)
+        .add_plugins(RepeatPlugin)
+        .add_plugins(MarkPlugin)
+        .add_plugins(MousePlugin)
+        .add_plugins(ViewportPlugin)
+        .add_plugins(WrapPlugin)
+        .add_plugins(SegmentPlugin)
+        .add_plugins(FoldPlugin)
+        .add_plugins(FinderPlugin)
+        .add_plugins(PalettePlugin)
+        .add_plugins(GrepPlugin)
+        .add_plugins(ExplorerPlugin)
+        .add_plugins(CaretPlugin)
+        .add_plugins(BreadcrumbPlugin)
+        .add_plugins(StructurePlugin)
+        .add_plugins(SettingsPlugin)
         .insert_resource(WinitSettings::desktop_app())
         .add_systems(Startup, (setup, setup_root_zipper).chain())
         .add_systems(Update, (
-            control_normal.run_if(in_state(AppState::Normal)),
-            control_travel.run_if(in_state(AppState::Travel)),
-            (control_insert, process_insert).run_if(in_state(AppState::Insert)),
+            (
+                control_modes.run_if(prompt_inactive),
+                control_normal.run_if(in_state(AppState::Normal).and_then(prompt_inactive)),
+                control_travel.run_if(in_state(AppState::Travel).and_then(prompt_inactive)),
+                control_insert.run_if(in_state(AppState::Insert).and_then(prompt_inactive)),
+            ).after(dispatch_keys),
+            // `.` replays Insert mode edits from Normal mode
+            process_insert
+                .run_if(in_state(AppState::Insert).or_else(in_state(AppState::Normal)))
+                .before(refocus),
             (move_char_left_right, move_char_up_down)
                 .before(goto_char)
                 .after(control_normal),
@@ -52,8 +144,10 @@ fn main() {
             despawn_zipper,
             move_zipper,
             goto_char.before(move_zipper),
-            keep_cursor_in_view.before(scroll),
             save_to_file,
+            reset_zipper,
+            refocus.before(move_zipper).after(build_structure),
+            jump_to_char.before(goto_char),
         ))
         .add_systems(OnEnter(AppState::Normal), setup_char_zipper)
         .add_systems(OnEnter(AppState::Insert), setup_char_zipper)
@@ -63,6 +157,8 @@ fn main() {
         .add_event::<DespawnZipper>()
         .add_event::<InsertChar>()
         .add_event::<Save>()
+        .add_event::<Refocus>()
+        .add_event::<JumpToChar>()
         .init_state::<AppState>()
         .run();
 }
@@ -105,9 +201,10 @@ fn setup(mut commands: Commands) {
     commands.spawn(PerfUiCompleteBundle::default());
 }
 
+#[allow(clippy::type_complexity)]
 fn setup_root_zipper(
     mut commands: Commands,
-    root_window_q: Query<Entity, (With<AppWindow>, Without<Parent>)>
+    root_window_q: Query<Entity, (With<AppWindow>, Without<Parent>, Without<Explorer>)>
 ) {
     let focus = root_window_q.single();
     commands.spawn((
@@ -117,12 +214,178 @@ fn setup_root_zipper(
     commands.entity(focus).insert(CurrentFocus);
 }
 
+/// Starts over from the window once the lines a zipper points into are replaced
+#[allow(clippy::type_complexity)]
+fn reset_zipper(
+    mut commands: Commands,
+    mut replace_evr: EventReader<ReplaceContent>,
+    mut next_state: ResMut<NextState<AppState>>,
+    root_zipp_q: Query<Entity, (With<ZipperType>, Without<Parent>)>,
+    focus_q: Query<Entity, With<CurrentFocus>>,
+    root_window_q: Query<Entity, (With<AppWindow>, Without<Parent>, Without<Explorer>)>
+) {
+    if replace_evr.read().last().is_none() { return }
+    for zipper in root_zipp_q.iter() {
+        commands.entity(zipper).despawn_recursive();
+    }
+    for focus in focus_q.iter() {
+        commands.entity(focus).remove::<CurrentFocus>();
+    }
+    let focus = root_window_q.single();
+    commands.spawn((
+        CurrentZipper,
+        RootZipperBundle::new(ZipperType::Window, focus)
+    ));
+    commands.entity(focus).insert(CurrentFocus);
+    next_state.set(AppState::Travel);
+}
+
+/// Rebuilds the zipper chain from the window down to an entity, for when the tree
+/// was edited out from under the zippers' sibling lists
+#[derive(Event)]
+pub struct Refocus(pub Entity);
+
+#[allow(clippy::too_many_arguments)]
+fn refocus(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    mut refocus_evr: EventReader<Refocus>,
+    mut move_inst_evw: EventWriter<MoveInstruction>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    structure_q: Query<&Structure>,
+    root_q: Query<&StructureRoot>,
+    node_q: Query<&StructureNode>,
+    root_zipp_q: Query<Entity, (With<ZipperType>, Without<Parent>)>,
+    focus_q: Query<Entity, With<CurrentFocus>>,
+    root_window_q: Query<Entity, (With<AppWindow>, Without<Parent>)>
+) {
+    let Some(Refocus(target)) = refocus_evr.read().last() else { return };
+    // Travel mode goes down a structured document through its nodes
+    let structured = match state.get() {
+        AppState::Travel => structure_path(*target, &structure_q, &root_q, &node_q, &parents_q, &children_q),
+        _ => None,
+    };
+    let path = match structured {
+        Some((document, nodes)) => tree_path(document, &parents_q, &children_q)
+            .map(|(root, path)| (root, path.into_iter().chain(nodes).collect())),
+        None => tree_path(*target, &parents_q, &children_q),
+    };
+    let Some((root, path)) = path else { return };
+    let Ok(window) = root_window_q.get(root) else { return };
+
+    for zipper in root_zipp_q.iter() {
+        commands.entity(zipper).despawn_recursive();
+    }
+    for focus in focus_q.iter() {
+        commands.entity(focus).remove::<CurrentFocus>();
+    }
+    commands.spawn((
+        CurrentZipper,
+        RootZipperBundle::new(ZipperType::Window, window)
+    ));
+    commands.entity(window).insert(CurrentFocus);
+    for index in path {
+        move_inst_evw.send(MoveInstruction::Child(index));
+    }
+}
+
+/// The root above an entity, and the child indices leading from it back down
+fn tree_path(
+    entity: Entity,
+    parents_q: &Query<&Parent>,
+    children_q: &Query<&Children>,
+) -> Option<(Entity, Vec<usize>)> {
+    let mut path = vec![];
+    let mut node = entity;
+    while let Ok(parent) = parents_q.get(node) {
+        let index = children_q
+            .get(**parent)
+            .ok()?
+            .iter()
+            .position(|child| *child == node)?;
+        path.push(index);
+        node = **parent;
+    }
+    path.reverse();
+    Some((node, path))
+}
+
+/// Moves the zipper onto a `Character` anywhere in the tree: up to the window,
+/// down to its line, then across with `GoToChar`. One in a buffer in the
+/// background is opened again first
+#[derive(Event, Clone, Copy)]
+pub struct JumpToChar(pub Entity);
+
+#[allow(clippy::too_many_arguments)]
+fn jump_to_char(
+    state: Res<State<AppState>>,
+    mut jump_evr: EventReader<JumpToChar>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut open_evw: EventWriter<OpenFile>,
+    mut move_inst_evw: EventWriter<MoveInstruction>,
+    mut goto_evw: EventWriter<GoToChar>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    widths_q: Query<&DisplayWidth>,
+    background_q: Query<&DocumentPath, With<Background>>,
+    curr_zipp_q: Query<Entity, With<CurrentZipper>>,
+    zippers_q: Query<&Parent, With<ZipperType>>,
+) {
+    let Some(JumpToChar(target)) = jump_evr.read().last() else { return };
+    let Ok(line_id) = parents_q
+        .get(*target)
+        .and_then(|span_id| parents_q.get(**span_id))
+    else { return };
+    if let Some((doc_id, path)) = parents_q
+        .get(**line_id)
+        .ok()
+        .and_then(|doc_id| Some((**doc_id, background_q.get(**doc_id).ok()?)))
+    {
+        let line = children_q.get(doc_id).map_or(0, |lines| {
+            lines.iter().position(|id| id == &**line_id).unwrap_or(0)
+        });
+        let column = line_characters(**line_id, &children_q)
+            .iter()
+            .position(|id| id == target)
+            .unwrap_or(0);
+        open_evw.send(OpenFile { path: path.to_path_buf(), position: Some((line, column)), record_jump: false });
+        return
+    }
+    // lines might not be on the way down while traveling, and there's no column to keep
+    if *state.get() == AppState::Travel {
+        refocus_evw.send(Refocus(*target));
+        return
+    }
+    let Some((_, path)) = tree_path(**line_id, &parents_q, &children_q) else { return };
+    let column = line_characters(**line_id, &children_q)
+        .iter()
+        .take_while(|id| *id != target)
+        .map(|id| widths_q.get(*id).map_or(1, |width| **width))
+        .sum();
+
+    let mut zipper = curr_zipp_q.single();
+    while let Ok(parent) = zippers_q.get(zipper) {
+        move_inst_evw.send(MoveInstruction::Parent);
+        zipper = **parent;
+    }
+    for index in path {
+        move_inst_evw.send(MoveInstruction::Child(index));
+    }
+    goto_evw.send(GoToChar(column, **line_id));
+}
+
 fn setup_char_zipper(
     mut move_inst_evw: EventWriter<MoveInstruction>,
-    mut next_state: ResMut<NextState<AppState>>,
     curr_zipp_q: Query<&ZipperType, With<CurrentZipper>>,
 ) {
     match curr_zipp_q.single() {
+        ZipperType::Window => {
+            move_inst_evw.send(MoveInstruction::Child(0));
+            move_inst_evw.send(MoveInstruction::Child(0));
+            move_inst_evw.send(MoveInstruction::Child(0));
+            move_inst_evw.send(MoveInstruction::Child(0));
+        },
         ZipperType::Document => {
             move_inst_evw.send(MoveInstruction::Child(0));
             move_inst_evw.send(MoveInstruction::Child(0));
@@ -135,186 +398,418 @@ fn setup_char_zipper(
         ZipperType::Span => {
             move_inst_evw.send(MoveInstruction::Child(0));
         },
-        ZipperType::Character => (),
-        _ => next_state.set(AppState::Travel),
+        // leaving Travel mode puts the zipper back on the lines
+        ZipperType::Character | ZipperType::Structure | ZipperType::Entry => (),
     }
 }
 
+/// `force` overwrites the file even if it changed on disk since it was read
 #[derive(Event)]
-pub struct Save;
+pub struct Save { force: bool }
 
+#[allow(clippy::type_complexity)]
 fn save_to_file(
     mut save_evr: EventReader<Save>,
+    mut status: ResMut<StatusMessage>,
     file_path: Res<WorkingFilePath>,
     text_q: Query<&Text, With<Character>>,
-    doc_q: Query<&Children, With<Document>>,
+    mut doc_q: Query<(&Children, &mut Modified, &mut DiskStamp), (With<Document>, Without<Background>)>,
     content_q: Query<&Children, Or<(With<Line>, With<Span>)>>,
 ) {
-    for _ in save_evr.read() {
-        let mut output = String::new();
-        let doc_children = doc_q.single();
-        for line_id in doc_children.iter() {
-            let line_children = content_q.get(*line_id).unwrap();
-            for span_id in line_children.iter() {
-                let span_children = content_q.get(*span_id).unwrap();
-                for ch_id in span_children.iter() {
-                    let character = text_q.get(*ch_id).unwrap();
-                    output.push_str(
-                        character
-                            .sections
-                            .iter()
-                            .fold(String::new(), |mut acc, s| {
-                                let ch = s.downcast_ref::<TextSection>().unwrap().value.as_str();
-                                acc.push_str(ch);
-                                acc
-                            })
-                            .as_str()
-                    )
-                }
-            }
-            output.push_str("\n");
+    for Save { force } in save_evr.read() {
+        let (doc_children, mut modified, mut stamp) = doc_q.single_mut();
+        if !force && stamp.is_stale(&file_path) {
+            **status = Some(format!(
+                "{} changed on disk since it was read (add ! to override)",
+                file_path.display(),
+            ));
+            continue
+        }
+        let output = document_text(doc_children, &content_q, &text_q);
+        match fs::write(file_path.clone(), output) {
+            Ok(()) => {
+                **modified = false;
+                **stamp = FileStamp::read(&file_path);
+                **status = Some(format!("{} written", file_path.display()));
+            },
+            Err(err) => **status = Some(format!("couldn't save {}: {err}", file_path.display())),
         }
-        fs::write(file_path.clone(), output).unwrap();
     }
 }
 
-fn control_normal(
-    mut char_input_evr: EventReader<ReceivedCharacter>,
-    mut char_movement_evw: EventWriter<MoveChar>,
+/// Mode switches and saving, bound the same way in every mode
+fn control_modes(
+    mut action_evr: EventReader<Action>,
     mut save_evw: EventWriter<Save>,
     mut next_state: ResMut<NextState<AppState>>,
-    keys: Res<ButtonInput<KeyCode>>,
 ) {
-    for char in char_input_evr.read() {
-        match char.char.as_str() {
-            "h" => { char_movement_evw.send(MoveChar::Left); },
-            "l" => { char_movement_evw.send(MoveChar::Right); },
-            "j" => { char_movement_evw.send(MoveChar::LineDown); },
-            "k" => { char_movement_evw.send(MoveChar::LineUp); },
-            "i" => next_state.set(AppState::Insert),
-            "t" if keys.pressed(KeyCode::ControlLeft) => next_state.set(AppState::Travel),
-            "s" if keys.pressed(KeyCode::ControlLeft) => { save_evw.send(Save); },
-            _ => ()
+    for action in action_evr.read() {
+        match action {
+            Action::NormalMode => next_state.set(AppState::Normal),
+            Action::InsertMode => next_state.set(AppState::Insert),
+            Action::TravelMode => next_state.set(AppState::Travel),
+            Action::CommandMode => next_state.set(AppState::Command),
+            Action::FindFile => next_state.set(AppState::Find),
+            Action::CommandPalette => next_state.set(AppState::Palette),
+            Action::Save => { save_evw.send(Save { force: false }); },
+            _ => (),
         }
     }
 }
 
-#[derive(Event)]
+fn control_normal(
+    mut action_evr: EventReader<Action>,
+    mut char_movement_evw: EventWriter<MoveChar>,
+    mut shift_evw: EventWriter<ShiftLine>,
+    mut match_evw: EventWriter<JumpToMatch>,
+) {
+    for action in action_evr.read() {
+        match action {
+            Action::MoveLeft => { char_movement_evw.send(MoveChar::Left); },
+            Action::MoveRight => { char_movement_evw.send(MoveChar::Right); },
+            Action::MoveDown => { char_movement_evw.send(MoveChar::LineDown); },
+            Action::MoveUp => { char_movement_evw.send(MoveChar::LineUp); },
+            Action::IndentLine => { shift_evw.send(ShiftLine::Indent); },
+            Action::DedentLine => { shift_evw.send(ShiftLine::Dedent); },
+            Action::JumpToMatch => { match_evw.send(JumpToMatch); },
+            _ => (),
+        }
+    }
+}
+
+#[derive(Event, Clone, Debug)]
 pub enum InsertChar{
     Str(String),
+    Tab,
+    Newline,
     Delete,
     ForwardDelete
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_insert(
     mut commands: Commands,
     mut insert_evr: EventReader<InsertChar>,
+    mut pending: Local<VecDeque<InsertChar>>,
+    mut refocus_evw: EventWriter<Refocus>,
+    indent: Res<IndentSettings>,
+    auto_pairs: Res<AutoPairs>,
+    file_path: Res<WorkingFilePath>,
     chars_q: Query<&Parent, With<Character>>,
+    indent_spans_q: Query<(), With<Indent>>,
+    mut graphemes_q: Query<(&mut Text, &mut DisplayWidth), With<Character>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    mut modified_q: Query<&mut Modified>,
     mut curr_zip_q: Query<(&ZipperType, &mut ZipperFocus, &mut ZipperSiblings), With<CurrentZipper>>,
     mut move_inst_evw: EventWriter<MoveInstruction>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
 ) {
-    for input in insert_evr.read() {
+    pending.extend(insert_evr.read().cloned());
+    while let Some(input) = pending.pop_front() {
         let (zipp_type, mut focus, mut siblings) = curr_zip_q.single_mut();
-        if *zipp_type != ZipperType::Character { return }
+        if *zipp_type != ZipperType::Character {
+            pending.clear();
+            return
+        }
         let curr_index = siblings.left.len();
         let span_id = chars_q.get(**focus).unwrap();
+        let line_id = parents_q.get(**span_id).unwrap();
+        mark_modified(**line_id, &parents_q, &mut modified_q);
+
+        let line_chars = line_characters(**line_id, &children_q);
+        let cursor = line_chars.iter().position(|id| *id == **focus).unwrap_or(0);
+        let before = &line_chars[..cursor];
+        let width = |id: &Entity| graphemes_q.get(*id).map_or(1, |(_, width)| **width);
+        let col = before.iter().map(width).sum::<usize>();
+        let text_of = |id: &Entity| graphemes_q
+            .get(*id)
+            .map(|(text, _)| character_str(text))
+            .unwrap_or_default();
+        let is_space = |id: &&Entity| text_of(id) == " ";
+        let is_blank = |id: &&Entity| text_of(id).trim().is_empty();
+
+        let pair_closer = match &input {
+            InsertChar::Str(str) => auto_pairs
+                .closer(&file_path, str)
+                // a quote straight after a word is an apostrophe
+                .filter(|close| close != str || !before
+                    .last()
+                    .is_some_and(|id| text_of(id).chars().all(char::is_alphanumeric)))
+                .map(str::to_string),
+            _ => None,
+        };
+        let skips_closer = matches!(&input, InsertChar::Str(str)
+            if auto_pairs.is_closer(&file_path, str)
+                && text_of(&focus) == *str);
+        let in_empty_pair = before
+            .last()
+            .and_then(|id| auto_pairs.closer(&file_path, &text_of(id)))
+            .is_some_and(|close| close == text_of(&focus));
+
         match input {
+            InsertChar::Str(_) if skips_closer => {
+                // typing a closer that's already there steps over it, to the line end if need be
+                let next = line_chars
+                    .get(cursor + 1)
+                    .copied()
+                    .unwrap_or_else(|| spawn_placeholder(&mut commands, **line_id));
+                refocus_evw.send(Refocus(next));
+                break
+            },
+            InsertChar::Str(str) if !before.is_empty()
+                && before.iter().all(|id| is_blank(&id))
+                && !indent_openers(&file_path).is_empty()
+                && INDENT_CLOSERS.contains(&str.as_str()) =>
+            {
+                // a closing bracket starting a line goes back one level
+                let columns = col.saturating_sub(indent.shiftwidth);
+                set_indent(
+                    &mut commands,
+                    **line_id,
+                    before,
+                    &indent.whitespace(columns),
+                    &parents_q,
+                    &children_q,
+                );
+                let index = siblings.left.iter().filter(|id| !before.contains(id)).count();
+                let closer = commands.spawn(CharacterBundle::new(&str)).id();
+                commands.entity(**span_id).insert_children(index, &[closer]);
+                refocus_evw.send(Refocus(**focus));
+                break
+            },
+            InsertChar::Str(str) if pair_closer.is_some() => {
+                let open = commands.spawn(CharacterBundle::new(&str)).id();
+                let close = commands.spawn(CharacterBundle::new(&pair_closer.unwrap())).id();
+                commands.entity(**span_id).insert_children(curr_index, &[open, close]);
+                siblings.left.push(open);
+                siblings.right.push_front(**focus);
+                commands.entity(**focus).remove::<CurrentFocus>();
+                *focus = ZipperFocus(close);
+                commands.entity(close).insert(CurrentFocus);
+            },
             InsertChar::Str(str) => {
-                let char_id = commands.spawn((
-                    Character,
-                    TextBundle::from_section(str, Default::default())
-                )).id();
-                commands.entity(**span_id).insert_children(curr_index, &[char_id]);
-                siblings.left.push(char_id);
+                let mut str = str.as_str();
+                // a combining mark or joiner extends the grapheme left of the cursor
+                if let Some((mut text, mut width)) = siblings.left
+                    .last()
+                    .and_then(|prev_id| graphemes_q.get_mut(*prev_id).ok())
+                {
+                    let prev = character_str(&text);
+                    let joined = format!("{prev}{str}");
+                    if let Some(first) = joined.graphemes(true).next() {
+                        if first.len() > prev.len() {
+                            *width = DisplayWidth::of(first);
+                            str = &str[first.len() - prev.len()..];
+                            *text = Text::from_section(first, Default::default());
+                        }
+                    }
+                }
+                let char_ids = str
+                    .graphemes(true)
+                    .map(|grapheme| commands.spawn(CharacterBundle::new(grapheme)).id())
+                    .collect::<Vec<_>>();
+                commands.entity(**span_id).insert_children(curr_index, &char_ids);
+                siblings.left.extend(char_ids);
             },
-            InsertChar::Delete => {
-                if siblings.left.len() != 0 {
-                    commands.entity(**span_id)
-                        .remove_children(&[siblings.left.pop().unwrap()]);
+            InsertChar::Tab => {
+                let whitespace = match indent.expandtab {
+                    true => {
+                        let step = indent.soft_step();
+                        " ".repeat(step - col % step)
+                    },
+                    false => "\t".into(),
+                };
+                let char_ids = whitespace
+                    .graphemes(true)
+                    .map(|grapheme| commands.spawn(CharacterBundle::new(grapheme)).id())
+                    .collect::<Vec<_>>();
+                commands.entity(**span_id).insert_children(curr_index, &char_ids);
+                siblings.left.extend(char_ids);
+            },
+            InsertChar::Newline => {
+                let doc_id = **parents_q.get(**line_id).unwrap();
+                let line_index = children_q
+                    .get(doc_id)
+                    .ok()
+                    .and_then(|lines| lines.iter().position(|id| id == &**line_id))
+                    .unwrap_or(0);
+                let base = before
+                    .iter()
+                    .take_while(is_blank)
+                    .map(width)
+                    .sum::<usize>();
+                let last_before = before.iter().rev().find(|id| !is_blank(id)).map(text_of);
+                let first_after = line_chars[cursor..].iter().find(|id| !is_blank(id)).map(text_of);
+                let columns = auto_indent(
+                    &indent,
+                    &file_path,
+                    base,
+                    last_before.as_deref(),
+                    first_after.as_deref(),
+                );
+
+                // everything from the cursor on moves down to the new line
+                let line_spans = children_q.get(**line_id).unwrap();
+                let span_index = line_spans.iter().position(|id| id == &**span_id).unwrap_or(0);
+                // breaking inside the indentation splits it, and the rest still indents the new line
+                let in_indent = indent_spans_q.contains(**span_id);
+                let mut moved = vec![];
+                if curr_index == 0 {
+                    moved.push(**span_id);
                 } else {
-                    commands.entity(**span_id)
-                        .remove_children(&[**focus]);
-                    if siblings.right.len() != 0 {
-                        *focus = ZipperFocus(siblings.right.pop_front().unwrap());
-                    } else {
-                        move_inst_evw.send(MoveInstruction::Parent);
+                    let tail = std::iter::once(**focus)
+                        .chain(siblings.right.iter().copied())
+                        .collect::<Vec<_>>();
+                    let mut tail_span = commands.spawn(SpanBundle::default());
+                    if in_indent {
+                        tail_span.insert(Indent);
                     }
+                    moved.push(tail_span.push_children(&tail).id());
+                }
+                moved.extend(line_spans.iter().skip(span_index + 1));
+
+                let new_line = commands.spawn(LineBundle::new(line_index + 2)).id();
+                commands.entity(new_line).push_children(&moved);
+                if !in_indent {
+                    let whitespace = indent.whitespace(columns);
+                    set_indent(&mut commands, new_line, &[], &whitespace, &parents_q, &children_q);
+                }
+                if cursor == 0 {
+                    spawn_placeholder(&mut commands, **line_id);
+                }
+                commands.entity(doc_id).insert_children(line_index + 1, &[new_line]);
+                refocus_evw.send(Refocus(**focus));
+                break
+            },
+            InsertChar::Delete if before.is_empty() => {
+                // backspace at the start of a line joins it onto the previous one
+                let doc_id = **parents_q.get(**line_id).unwrap();
+                let Some(prev_line) = children_q
+                    .get(doc_id)
+                    .ok()
+                    .and_then(|lines| {
+                        let index = lines.iter().position(|id| id == &**line_id)?;
+                        lines.get(index.checked_sub(1)?).copied()
+                    })
+                else { continue };
+                let prev_chars = line_characters(prev_line, &children_q);
+                let is_placeholder = |id: &&Entity| text_of(id).is_empty();
+                if line_chars.iter().all(|id| is_placeholder(&id)) {
+                    commands.entity(**line_id).despawn_recursive();
+                    if let Some(last) = prev_chars.last() {
+                        refocus_evw.send(Refocus(*last));
+                    }
+                    break
+                }
+                let placeholders = prev_chars.iter().filter(is_placeholder).copied().collect::<Vec<_>>();
+                remove_characters(&mut commands, &placeholders, &parents_q, &children_q);
+                let line_spans = children_q.get(**line_id).unwrap().to_vec();
+                for span_id in line_spans.iter() {
+                    commands.entity(*span_id).remove::<Indent>();
+                }
+                commands.entity(prev_line).push_children(&line_spans);
+                commands.entity(**line_id).despawn_recursive();
+                refocus_evw.send(Refocus(**focus));
+                break
+            },
+            InsertChar::Delete if in_empty_pair => {
+                // backspace between a freshly typed pair removes both halves
+                let open = before[cursor - 1];
+                remove_characters(&mut commands, &[open, **focus], &parents_q, &children_q);
+                let next = line_chars.get(cursor + 1).or(before.iter().rev().nth(1)).copied();
+                let next = next.unwrap_or_else(|| spawn_placeholder(&mut commands, **line_id));
+                refocus_evw.send(Refocus(next));
+                break
+            },
+            InsertChar::Delete => {
+                // spaces go back to the previous soft tab stop in one go, as Tab put them in
+                let mut count = 1;
+                if before.last().is_some_and(|id| is_space(&id)) {
+                    let step = indent.soft_step();
+                    let stop = (col - 1) / step * step;
+                    count = before
+                        .iter()
+                        .rev()
+                        .take_while(is_space)
+                        .take(col - stop)
+                        .count();
+                }
+                if count == 1 && !siblings.left.is_empty() {
+                    commands.entity(siblings.left.pop().unwrap()).despawn_recursive();
+                } else {
+                    remove_characters(&mut commands, &before[cursor - count..], &parents_q, &children_q);
+                    refocus_evw.send(Refocus(**focus));
+                    // the zipper has to be rebuilt before anything else can be edited
+                    break
                 }
             },
             InsertChar::ForwardDelete => {
-                commands.entity(**span_id)
-                    .remove_children(&[**focus]);
-                if siblings.right.len() != 0 {
+                commands.entity(**focus).despawn_recursive();
+                if !siblings.right.is_empty() {
                     *focus = ZipperFocus(siblings.right.pop_front().unwrap());
-                } else if siblings.left.len() != 0 {
+                    commands.entity(**focus).insert(CurrentFocus);
+                } else if !siblings.left.is_empty() {
                     *focus = ZipperFocus(siblings.left.pop().unwrap());
+                    commands.entity(**focus).insert(CurrentFocus);
                 } else {
                     move_inst_evw.send(MoveInstruction::Parent);
+                    break
                 }
             },
         }
     }
+    // the rest waits for the rebuilt zipper, which takes a frame
+    if !pending.is_empty() {
+        redraw_evw.send(RequestRedraw);
+    }
 }
 
 fn control_insert(
-    mut save_evw: EventWriter<Save>,
-    mut char_input_evr: EventReader<ReceivedCharacter>,
-    mut keyb_input_evr: EventReader<KeyboardInput>,
+    mut action_evr: EventReader<Action>,
+    mut text_evr: EventReader<TypedText>,
     mut insert_evw: EventWriter<InsertChar>,
-    mut next_state: ResMut<NextState<AppState>>,
-    keys: Res<ButtonInput<KeyCode>>,
 ) {
-    for key in keyb_input_evr.read() {
-        use KeyCode::*;
-        use ButtonState::*;
-        match (key.key_code, key.state) {
-            (Escape, Pressed) => {
-                next_state.set(AppState::Normal);
-                char_input_evr.clear();
-            }
-            (Delete, Pressed) => {
-                insert_evw.send(InsertChar::ForwardDelete);
-                char_input_evr.clear();
-            },
-            (Backspace, Pressed) => {
-                insert_evw.send(InsertChar::Delete);
-                char_input_evr.clear();
-            },
+    for action in action_evr.read() {
+        match action {
+            Action::Newline => { insert_evw.send(InsertChar::Newline); },
+            Action::Backspace => { insert_evw.send(InsertChar::Delete); },
+            Action::DeleteForward => { insert_evw.send(InsertChar::ForwardDelete); },
+            Action::Tab => { insert_evw.send(InsertChar::Tab); },
             _ => (),
         }
     }
-
-    for char in char_input_evr.read() {
-        if keys.pressed(KeyCode::ControlLeft) {
-            match char.char.as_str() {
-                "t" => next_state.set(AppState::Travel),
-                "s" => { save_evw.send(Save); },
-                _ => (),
-            }
-            return;
-        }
-        insert_evw.send(InsertChar::Str(char.char.to_string()));
+    for TypedText(str) in text_evr.read() {
+        insert_evw.send(InsertChar::Str(str.clone()));
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn control_travel(
-    mut char_input_evr: EventReader<ReceivedCharacter>,
-    mut save_evw: EventWriter<Save>,
+    mut action_evr: EventReader<Action>,
     mut zipper_movement_evw: EventWriter<MoveInstruction>,
-    mut next_state: ResMut<NextState<AppState>>,
-    keys: Res<ButtonInput<KeyCode>>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus, Option<&ZipperSiblings>), With<CurrentZipper>>,
+    children_q: Query<&Children>,
+    skipped_q: Query<(), Or<(With<Indent>, With<EntryLabel>)>>,
 ) {
-    if keys.just_pressed(KeyCode::Escape) {
-        next_state.set(AppState::Normal);
-    }
-    for char in char_input_evr.read() {
-        match char.char.as_str() {
-            "s" if keys.pressed(KeyCode::ControlLeft) => { save_evw.send(Save); },
-            "h" | "a" => { zipper_movement_evw.send(MoveInstruction::Left); },
-            "l" | "d" => { zipper_movement_evw.send(MoveInstruction::Right); },
-            "j" | "w" => { zipper_movement_evw.send(MoveInstruction::Child(0)); },
-            "k" | "s" => { zipper_movement_evw.send(MoveInstruction::Parent); },
-            "i" => next_state.set(AppState::Insert),
+    // indentation is skipped over when traveling between a line's spans, and
+    // labels between a directory's entries
+    let (zip_type, focus, siblings) = curr_zipp_q.single();
+    let first_child = match (zip_type, children_q.get(**focus)) {
+        (ZipperType::Line | ZipperType::Window | ZipperType::Entry, Ok(children))
+            if children.len() > 1 && skipped_q.contains(children[0]) => 1,
+        _ => 0,
+    };
+    let left_is_skipped = siblings
+        .and_then(|siblings| siblings.left.last())
+        .is_some_and(|id| skipped_q.contains(*id));
+    for action in action_evr.read() {
+        match action {
+            Action::ZipperLeft if matches!(zip_type, ZipperType::Span | ZipperType::Entry) && left_is_skipped => (),
+            Action::ZipperLeft => { zipper_movement_evw.send(MoveInstruction::Left); },
+            Action::ZipperRight => { zipper_movement_evw.send(MoveInstruction::Right); },
+            Action::ZipperChild => { zipper_movement_evw.send(MoveInstruction::Child(first_child)); },
+            Action::ZipperParent => { zipper_movement_evw.send(MoveInstruction::Parent); },
             _ => ()
         }
     }
@@ -333,18 +828,18 @@ fn move_char_left_right(
         let par_sibs = zippers_q.get(**zip_parent).unwrap();
         match movement {
             MoveChar::Left => {
-                if siblings.left.len() > 0 {
+                if !siblings.left.is_empty() {
                     move_zipp_evw.send(MoveInstruction::Left);
-                } else if par_sibs.left.len() > 0 {
+                } else if !par_sibs.left.is_empty() {
                     move_zipp_evw.send(MoveInstruction::Parent);
                     move_zipp_evw.send(MoveInstruction::Left);
                     move_zipp_evw.send(MoveInstruction::Child(usize::MAX));
                 }
             },
             MoveChar::Right => {
-                if siblings.right.len() > 0 {
+                if !siblings.right.is_empty() {
                     move_zipp_evw.send(MoveInstruction::Right);
-                } else if par_sibs.right.len() > 0 {
+                } else if !par_sibs.right.is_empty() {
                     move_zipp_evw.send(MoveInstruction::Parent);
                     move_zipp_evw.send(MoveInstruction::Right);
                     move_zipp_evw.send(MoveInstruction::Child(0));
@@ -355,31 +850,36 @@ fn move_char_left_right(
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn move_char_up_down (
     mut move_char_evr: EventReader<MoveChar>,
     mut move_zipp_evw: EventWriter<MoveInstruction>,
     mut move_line_evr: EventWriter<GoToChar>,
     main_q: Query<&Children, Or<(With<Line>, With<Span>)>>,
+    widths_q: Query<&DisplayWidth>,
     zippers_q: Query<(&Parent, &ZipperSiblings)>,
     curr_zipp_q: Query<(&Parent, &ZipperType, &ZipperSiblings), With<CurrentZipper>>,
+    folded_q: Query<(), With<FoldedAway>>,
 ) {
+    let width = |id: &Entity| widths_q.get(*id).map_or(1, |width| **width);
+    // `MoveInstruction` passes over the same lines
+    let shown = |id: &&Entity| !folded_q.contains(**id);
     for movement in move_char_evr.read() {
         if *movement == MoveChar::Left || *movement == MoveChar::Right { return }
         let (parent, zip_type, siblings) = curr_zipp_q.single();
         if *zip_type != ZipperType::Character { return }
         let (span_zip_par, span_zip_sibs) = zippers_q.get(**parent).unwrap();
-        let mut curr_pos = span_zip_sibs.left.iter().fold(0_usize, |acc, sib| {
-            let id = sib.downcast_ref::<Entity>().unwrap();
-            let span = main_q.get(*id).unwrap();
-            acc + span.len()
+        let mut curr_pos = span_zip_sibs.left.iter().fold(0_usize, |acc, span_id| {
+            let span = main_q.get(*span_id).unwrap();
+            acc + span.iter().map(width).sum::<usize>()
         });
-        curr_pos += siblings.left.len();
+        curr_pos += siblings.left.iter().map(width).sum::<usize>();
 
         let (_, line_zip_sibs) = zippers_q.get(**span_zip_par).unwrap();
 
         match movement {
             MoveChar::LineUp => {
-                if let Some(line_id) = line_zip_sibs.left.last() {
+                if let Some(line_id) = line_zip_sibs.left.iter().rev().find(shown) {
                     move_zipp_evw.send(MoveInstruction::Parent);
                     move_zipp_evw.send(MoveInstruction::Parent);
                     move_zipp_evw.send(MoveInstruction::Left);
@@ -387,7 +887,7 @@ fn move_char_up_down (
                 }
             },
             MoveChar::LineDown => {
-                if let Some(line_id) = line_zip_sibs.right.front() {
+                if let Some(line_id) = line_zip_sibs.right.iter().find(shown) {
                     move_zipp_evw.send(MoveInstruction::Parent);
                     move_zipp_evw.send(MoveInstruction::Parent);
                     move_zipp_evw.send(MoveInstruction::Right);
@@ -399,41 +899,44 @@ fn move_char_up_down (
     }
 }
 
+/// Focuses the `Character` covering a display column of a line
 #[derive(Event)]
 pub struct GoToChar(usize, Entity);
 
+#[allow(clippy::type_complexity)]
 fn goto_char(
     mut char_evr: EventReader<GoToChar>,
     mut zipper_movement_evw: EventWriter<MoveInstruction>,
     main_q: Query<&Children, Or<(With<Line>, With<Span>)>>,
+    widths_q: Query<&DisplayWidth>,
 ) {
+    let width = |id: &Entity| widths_q.get(*id).map_or(1, |width| **width);
     'event: for GoToChar(position, line_id) in char_evr.read() {
         let line_children = main_q.get(*line_id).unwrap();
-        let mut curr_char_pos = 0_usize;
-        let mut span_count = 0_usize;
-        for span_id in line_children.iter() {
+        let mut curr_col = 0_usize;
+        for (span_count, span_id) in line_children.iter().enumerate() {
             let span_children = main_q.get(*span_id).unwrap();
-            if curr_char_pos + span_children.len() > *position {
+            let span_width = span_children.iter().map(width).sum::<usize>();
+            if curr_col + span_width > *position {
                 zipper_movement_evw.send(MoveInstruction::Child(span_count));
-                let mut char_count = 0_usize;
-                for _ in span_children.iter() {
-                    if curr_char_pos + char_count == *position {
+                for (char_count, char_id) in span_children.iter().enumerate() {
+                    curr_col += width(char_id);
+                    if curr_col > *position {
                         zipper_movement_evw.send(MoveInstruction::Child(char_count));
                         continue 'event;
                     }
-                    char_count += 1;
                 }
                 zipper_movement_evw.send(MoveInstruction::Child(usize::MAX));
                 continue 'event;
-        }
-            curr_char_pos += span_children.len();
-            span_count += 1;
+            }
+            curr_col += span_width;
         }
         zipper_movement_evw.send(MoveInstruction::Child(usize::MAX));
         zipper_movement_evw.send(MoveInstruction::Child(usize::MAX));
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn move_zipper(
     world: &mut World,
     mut state: Local<SystemState<(
@@ -459,10 +962,17 @@ fn move_zipper(
                 With<Span>,
             )>
         >,
+        Res<State<AppState>>,
+        Query<&Structure>,
+        Query<&StructureNode>,
+        Query<&Children>,
+        Query<&Parent>,
+        Query<(), With<FoldedAway>>,
+        Query<(&Entry, Has<Expanded>)>,
     )>>
 ) {
     let mut inst_events = Vec::with_capacity(5);
-    let (_, mut events, _, _, _) = state.get_mut(world);
+    let (_, mut events, ..) = state.get_mut(world);
     for i in events.read() { inst_events.push(*i) }
 
     for inst in inst_events.into_iter() {
@@ -472,19 +982,31 @@ fn move_zipper(
                 _,
                 mut curr_zipper_q,
                 zippers_q,
-                app_tree_q
+                app_tree_q,
+                app_state,
+                structure_q,
+                node_q,
+                children_q,
+                parents_q,
+                folded_q,
+                entry_q,
             ) = state.get_mut(world);
+            // closed folds are passed over, so one counts as a single node
+            let shown = |id: &Entity| !folded_away(*id, &folded_q, &node_q, &parents_q);
             match inst {
                 MoveInstruction::Left => {
                     let (_, mut curr_focus, _, siblings, _) = curr_zipper_q.single_mut();
                     if siblings.is_none() { return }
                     // adjust focus and siblings
                     let mut sibs = siblings.unwrap();
-                    if sibs.clone().left.len() == 0 { return }
+                    let Some(index) = sibs.left.iter().rposition(shown) else { return };
 
                     commands.entity(**curr_focus).remove::<CurrentFocus>();
 
                     sibs.right.push_front(**curr_focus);
+                    for passed in sibs.left.drain(index + 1..).rev().collect::<Vec<_>>() {
+                        sibs.right.push_front(passed);
+                    }
                     *curr_focus = ZipperFocus(sibs.left.pop().unwrap());
 
                     commands.entity(**curr_focus).insert(CurrentFocus);
@@ -494,11 +1016,13 @@ fn move_zipper(
                     if siblings.is_none() { return }
                     // adjust focus and siblings
                     let mut sibs = siblings.unwrap();
-                    if sibs.clone().right.len() == 0 { return }
+                    let Some(index) = sibs.right.iter().position(shown) else { return };
 
                     commands.entity(**curr_focus).remove::<CurrentFocus>();
 
                     sibs.left.push(**curr_focus);
+                    let passed = sibs.right.drain(..index).collect::<Vec<_>>();
+                    sibs.left.extend(passed);
                     *curr_focus = ZipperFocus(sibs.right.pop_front().unwrap());
 
                     commands.entity(**curr_focus).insert(CurrentFocus);
@@ -518,14 +1042,23 @@ fn move_zipper(
                     if curr_zipper_q.is_empty() { return }
                     let (curr_id, curr_focus, curr_type, _, _,) = curr_zipper_q.single();
 
-                    match curr_type {
-                        ZipperType::Character => return,
-                        _ => (),
-                    };
+                    if *curr_type == ZipperType::Character { return }
 
-                    let curr_zipper_children = app_tree_q.get(**curr_focus).unwrap();
+                    // a structured document is traveled through its nodes instead of its lines,
+                    // and the explorer through the entries of expanded directories
+                    let structured = match app_state.get() {
+                        AppState::Travel => structure_children(**curr_focus, &structure_q, &node_q, &children_q),
+                        _ => None,
+                    }.or_else(|| explorer_children(**curr_focus, &entry_q, &children_q));
+                    let (child_type, curr_zipper_children) = match structured {
+                        Some((child_type, children)) => (child_type, children),
+                        None => match app_tree_q.get(**curr_focus) {
+                            Ok(children) => (curr_type.child_type(), children.to_vec()),
+                            Err(_) => return,
+                        },
+                    };
 
-                    if curr_zipper_children.len() == 0 { return }
+                    if curr_zipper_children.is_empty() { return }
 
                     let index = min(index, curr_zipper_children.len() - 1);
                     let (left, right_tmp) = curr_zipper_children.split_at(index);
@@ -538,7 +1071,7 @@ fn move_zipper(
                     let new_zip_id = commands.spawn((
                         CurrentZipper,
                         BranchZipperBundle::new(
-                            curr_type.child_type(),
+                            child_type,
                             new_focus,
                             left.into(),
                             right.to_vec().into(),
@@ -561,14 +1094,20 @@ fn despawn_zipper(
     }
 }
 
+/// Outlines the focus, unless it's a character that gets the caret instead
 fn highlight_border(
     mut commands: Commands,
-    new_focus: Query<Entity, Added<CurrentFocus>>,
+    settings: Res<Settings>,
+    state: Res<State<AppState>>,
+    new_focus: Query<(), Added<CurrentFocus>>,
+    focus_q: Query<(Entity, Has<Character>), With<CurrentFocus>>,
 ) {
-    for curr_focus in new_focus.iter() {
-        commands
-            .entity(curr_focus)
-            .insert(Outline::new(Val::Px(1.), Val::Px(0.), Color::WHITE));
+    if new_focus.is_empty() && !state.is_changed() { return }
+    for (curr_focus, is_char) in focus_q.iter() {
+        match is_char && shows_caret(state.get()) {
+            true => { commands.entity(curr_focus).remove::<Outline>(); },
+            false => { commands.entity(curr_focus).insert(Outline::new(Val::Px(1.), Val::Px(0.), *settings.cursor)); },
+        }
     }
 }
 
@@ -577,31 +1116,10 @@ fn dehighlight_border(
     mut removed: RemovedComponents<CurrentFocus>,
 ) {
     for id in removed.read() {
-        commands
-            .entity(id)
-            .remove::<Outline>();
-    }
-}
-
-fn keep_cursor_in_view(
-    mut scroll_evw: EventWriter<Scroll>,
-    app_tree_q: Query<&GlobalTransform, With<Node>>,
-    curr_zipp_q: Query<&ZipperFocus, Added<CurrentZipper>>,
-    cam_q: Query<&OrthographicProjection>,
-) {
-    if curr_zipp_q.is_empty() { return }
-
-    let cam_proj = cam_q.single();
-    let g_translation = app_tree_q
-        .get(**curr_zipp_q.single()).unwrap()
-        .compute_transform()
-        .translation;
-
-    let cam_offset = cam_proj.area.max.y;
-    if g_translation.y < cam_proj.area.min.y + cam_offset {
-        scroll_evw.send(Scroll(12.));
-    } else if g_translation.y > cam_proj.area.max.y + cam_offset {
-        scroll_evw.send(Scroll(-12.));
+        // the focus may have been despawned rather than moved off
+        if let Some(mut entity) = commands.get_entity(id) {
+            entity.remove::<Outline>();
+        }
     }
 }
 
@@ -612,6 +1130,10 @@ pub enum ZipperType {
     Line,
     Span,
     Character,
+    /// A node of a document's `Structure`, only reached while traveling
+    Structure,
+    /// A file or directory in the explorer sidebar
+    Entry,
 }
 
 impl ZipperType {
@@ -622,6 +1144,8 @@ impl ZipperType {
             ZipperType::Line => ZipperType::Span,
             ZipperType::Span => ZipperType::Character,
             ZipperType::Character => ZipperType::Character,
+            ZipperType::Structure => ZipperType::Structure,
+            ZipperType::Entry => ZipperType::Entry,
         }
     }
 }
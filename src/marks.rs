@@ -0,0 +1,394 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::cmdline::StatusMessage;
+use crate::keymap::{Action, KeyedAction};
+use crate::text_components::{
+    character_str, line_characters, Background, Character, Document, DocumentPath, OpenFile, WorkingFilePath
+};
+use crate::{CurrentZipper, JumpToChar, ZipperFocus, ZipperType};
+
+/// Older jumps fall off the start of the list
+const JUMP_LIST_LEN: usize = 100;
+
+pub struct MarkPlugin;
+
+impl Plugin for MarkPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, load_file_marks)
+            .add_systems(Update, (
+                (set_mark, jump_to_mark, jump_motions, walk_jump_list, forget_local_marks),
+                long_jump,
+            ).chain())
+            .add_systems(Last, save_file_marks_on_exit)
+            .init_resource::<Marks>()
+            .init_resource::<JumpList>()
+            .add_event::<LongJump>();
+    }
+}
+
+/// A position that follows its text around as lines are edited. Once the
+/// character is gone the mark falls back to its line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mark {
+    pub char_id: Entity,
+    pub line_id: Entity,
+}
+
+impl Mark {
+    /// The character or, failing that, the line the zipper is on
+    pub fn focused(
+        zipper: (&ZipperType, &ZipperFocus),
+        parents_q: &Query<&Parent>,
+        children_q: &Query<&Children>,
+    ) -> Option<Self> {
+        let (zip_type, focus) = zipper;
+        let line_id = match zip_type {
+            ZipperType::Character => **parents_q.get(**parents_q.get(**focus).ok()?).ok()?,
+            ZipperType::Span => **parents_q.get(**focus).ok()?,
+            ZipperType::Line => **focus,
+            _ => return None,
+        };
+        let char_id = match zip_type {
+            ZipperType::Character => **focus,
+            ZipperType::Span => *children_q.get(**focus).ok()?.first()?,
+            _ => *line_characters(line_id, children_q).first()?,
+        };
+        Some(Self { char_id, line_id })
+    }
+
+    /// Where the mark points now, if its text is still around
+    pub fn target(
+        &self,
+        chars_q: &Query<(), With<Character>>,
+        children_q: &Query<&Children>,
+    ) -> Option<Entity> {
+        if chars_q.contains(self.char_id) {
+            Some(self.char_id)
+        } else {
+            line_characters(self.line_id, children_q).first().copied()
+        }
+    }
+}
+
+/// An uppercase mark, kept across sessions by file, line and character
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileMark {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// `a-z` are local to the buffer they're set in, `A-Z` remember their file as well
+#[derive(Resource, Default)]
+pub struct Marks {
+    /// By document, then name
+    local: HashMap<(Entity, char), Mark>,
+    file: HashMap<char, FileMark>,
+    /// Uppercase marks set this session, which are kept up to date through edits
+    live: HashMap<char, Mark>,
+}
+
+impl Marks {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("diy-ed").join("marks.toml"))
+    }
+
+    fn save(&self) {
+        let Some(path) = Self::path() else { return };
+        let marks = self.file
+            .iter()
+            .map(|(name, mark)| (name.to_string(), mark.clone()))
+            .collect::<HashMap<_, _>>();
+        let Ok(source) = toml::to_string(&marks) else { return };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, source);
+    }
+}
+
+/// Moves in steps through where big jumps came from
+#[derive(Resource, Default)]
+pub struct JumpList {
+    entries: Vec<Mark>,
+    index: usize,
+}
+
+impl JumpList {
+    pub fn push(&mut self, mark: Mark) {
+        // one entry per line, like vim
+        self.entries.retain(|entry| entry.line_id != mark.line_id);
+        self.entries.push(mark);
+        if self.entries.len() > JUMP_LIST_LEN {
+            self.entries.remove(0);
+        }
+        self.index = self.entries.len();
+    }
+}
+
+/// A `JumpToChar` that's recorded in the jump list, for marks, `gg`, `G` and searches
+#[derive(Event, Clone, Copy)]
+pub struct LongJump(pub Entity);
+
+/// 1-based line and 0-based character index of a character within its document
+fn file_position(
+    char_id: Entity,
+    parents_q: &Query<&Parent>,
+    children_q: &Query<&Children>,
+) -> Option<(usize, usize)> {
+    let line_id = **parents_q.get(**parents_q.get(char_id).ok()?).ok()?;
+    let doc_id = **parents_q.get(line_id).ok()?;
+    let line = children_q.get(doc_id).ok()?.iter().position(|id| *id == line_id)?;
+    let column = line_characters(line_id, children_q).iter().position(|id| *id == char_id)?;
+    Some((line + 1, column))
+}
+
+fn load_file_marks(mut marks: ResMut<Marks>) {
+    let Some(path) = Marks::path() else { return };
+    let Ok(source) = fs::read_to_string(path) else { return };
+    let Ok(file) = toml::from_str::<HashMap<String, FileMark>>(&source) else { return };
+    marks.file = file
+        .into_iter()
+        .filter_map(|(name, mark)| Some((name.chars().next()?, mark)))
+        .collect();
+}
+
+fn set_mark(
+    mut keyed_evr: EventReader<KeyedAction>,
+    mut marks: ResMut<Marks>,
+    mut status: ResMut<StatusMessage>,
+    file_path: Res<WorkingFilePath>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+) {
+    for KeyedAction { action, key } in keyed_evr.read() {
+        if *action != Action::SetMark { continue }
+        let Some(mark) = Mark::focused(curr_zipp_q.single(), &parents_q, &children_q) else { continue };
+        match key {
+            'a'..='z' => {
+                let Ok(doc_id) = parents_q.get(mark.line_id) else { continue };
+                marks.local.insert((**doc_id, *key), mark);
+            },
+            'A'..='Z' => {
+                let Some((line, column)) = file_position(mark.char_id, &parents_q, &children_q)
+                else { continue };
+                let path = file_path.canonicalize().unwrap_or_else(|_| file_path.to_path_buf());
+                marks.file.insert(*key, FileMark { path, line, column });
+                marks.live.insert(*key, mark);
+                marks.save();
+            },
+            _ => **status = Some(format!("Invalid mark {key}")),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn jump_to_mark(
+    mut keyed_evr: EventReader<KeyedAction>,
+    mut jump_evw: EventWriter<LongJump>,
+    mut open_evw: EventWriter<OpenFile>,
+    marks: Res<Marks>,
+    mut status: ResMut<StatusMessage>,
+    doc_q: Query<(&DocumentPath, &Children), With<Document>>,
+    shown_q: Query<Entity, (With<Document>, Without<Background>)>,
+    chars_q: Query<(), With<Character>>,
+    text_q: Query<&Text, With<Character>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+) {
+    for KeyedAction { action, key } in keyed_evr.read() {
+        let whole_line = match action {
+            Action::JumpToMark => false,
+            Action::JumpToMarkLine => true,
+            _ => continue,
+        };
+        let local = shown_q.get_single().ok().and_then(|doc_id| marks.local.get(&(doc_id, *key)));
+        let mark = match local.or(marks.live.get(key)) {
+            Some(mark) => *mark,
+            None => {
+                let Some(file_mark) = marks.file.get(key) else {
+                    **status = Some(format!("Mark not set: {key}"));
+                    continue
+                };
+                // an uppercase mark from another session, in a buffer that's open or read from disk
+                let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                let open_lines = doc_q
+                    .iter()
+                    .find(|(doc_path, _)| canonical(doc_path) == file_mark.path)
+                    .map(|(_, lines)| lines);
+                let line_index = file_mark.line.saturating_sub(1);
+                if let Some(lines) = open_lines {
+                    let Some(line_id) = lines.get(line_index).or(lines.last()).copied() else { continue };
+                    let line_chars = line_characters(line_id, &children_q);
+                    let target = match whole_line {
+                        true => first_nonblank(line_id, &text_q, &children_q),
+                        false => line_chars.get(file_mark.column).or(line_chars.last()).copied(),
+                    };
+                    if let Some(target) = target {
+                        jump_evw.send(LongJump(target));
+                    }
+                    continue
+                }
+                let Ok(content) = fs::read_to_string(&file_mark.path) else {
+                    **status = Some(format!("Can't open {}", file_mark.path.display()));
+                    continue
+                };
+                let column = match whole_line {
+                    true => content
+                        .split('\n')
+                        .nth(line_index)
+                        .map_or(0, |line| line
+                            .graphemes(true)
+                            .take_while(|ch| ch.trim().is_empty())
+                            .count()),
+                    false => file_mark.column,
+                };
+                open_evw.send(OpenFile {
+                    path: file_mark.path.clone(),
+                    position: Some((line_index, column)),
+                    record_jump: true,
+                });
+                continue
+            },
+        };
+        let Some(mut target) = mark.target(&chars_q, &children_q) else {
+            **status = Some(format!("Mark {key} was deleted"));
+            continue
+        };
+        if whole_line {
+            // the character may have moved onto another line, by joining lines
+            let line_id = parents_q
+                .get(target)
+                .and_then(|span_id| parents_q.get(**span_id))
+                .map_or(mark.line_id, |line_id| **line_id);
+            target = first_nonblank(line_id, &text_q, &children_q).unwrap_or(target);
+        }
+        // a mark in a buffer in the background opens it again
+        jump_evw.send(LongJump(target));
+    }
+}
+
+/// Drops the lowercase marks of buffers that are gone
+fn forget_local_marks(mut removed: RemovedComponents<Document>, mut marks: ResMut<Marks>) {
+    for doc_id in removed.read() {
+        marks.local.retain(|(mark_doc, _), _| *mark_doc != doc_id);
+    }
+}
+
+/// The first character on a line that isn't indentation
+fn first_nonblank(
+    line_id: Entity,
+    text_q: &Query<&Text, With<Character>>,
+    children_q: &Query<&Children>,
+) -> Option<Entity> {
+    let line_chars = line_characters(line_id, children_q);
+    line_chars
+        .iter()
+        .find(|id| text_q.get(**id).is_ok_and(|text| !character_str(text).trim().is_empty()))
+        .or(line_chars.first())
+        .copied()
+}
+
+/// `gg` and `G`
+fn jump_motions(
+    mut action_evr: EventReader<Action>,
+    mut jump_evw: EventWriter<LongJump>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    text_q: Query<&Text, With<Character>>,
+) {
+    for action in action_evr.read() {
+        if !matches!(action, Action::FirstLine | Action::LastLine) { continue }
+        let Some(mark) = Mark::focused(curr_zipp_q.single(), &parents_q, &children_q) else { continue };
+        let Ok(doc_id) = parents_q.get(mark.line_id) else { continue };
+        let Ok(lines) = children_q.get(**doc_id) else { continue };
+        let line_id = match action {
+            Action::FirstLine => lines.first(),
+            _ => lines.last(),
+        };
+        if let Some(target) = line_id.and_then(|line_id| first_nonblank(*line_id, &text_q, &children_q)) {
+            jump_evw.send(LongJump(target));
+        }
+    }
+}
+
+fn walk_jump_list(
+    mut action_evr: EventReader<Action>,
+    mut jump_evw: EventWriter<JumpToChar>,
+    mut jumps: ResMut<JumpList>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    chars_q: Query<(), With<Character>>,
+) {
+    for action in action_evr.read() {
+        match action {
+            Action::JumpBack => {
+                // coming back from the end of the list returns to where we are now
+                if jumps.index == jumps.entries.len() {
+                    let Some(here) = Mark::focused(curr_zipp_q.single(), &parents_q, &children_q)
+                    else { continue };
+                    jumps.push(here);
+                    jumps.index = jumps.entries.len() - 1;
+                }
+                while jumps.index > 0 {
+                    jumps.index -= 1;
+                    if let Some(target) = jumps.entries[jumps.index].target(&chars_q, &children_q) {
+                        jump_evw.send(JumpToChar(target));
+                        break
+                    }
+                }
+            },
+            Action::JumpForward => {
+                while jumps.index + 1 < jumps.entries.len() {
+                    jumps.index += 1;
+                    if let Some(target) = jumps.entries[jumps.index].target(&chars_q, &children_q) {
+                        jump_evw.send(JumpToChar(target));
+                        break
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+fn long_jump(
+    mut long_jump_evr: EventReader<LongJump>,
+    mut jump_evw: EventWriter<JumpToChar>,
+    mut jumps: ResMut<JumpList>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+) {
+    let Some(LongJump(target)) = long_jump_evr.read().last() else { return };
+    if let Some(here) = Mark::focused(curr_zipp_q.single(), &parents_q, &children_q) {
+        jumps.push(here);
+    }
+    jump_evw.send(JumpToChar(*target));
+}
+
+fn save_file_marks_on_exit(
+    mut exit_evr: EventReader<AppExit>,
+    mut marks: ResMut<Marks>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+) {
+    if exit_evr.read().last().is_none() { return }
+    // uppercase marks moved along with any edits made since they were set
+    let live = marks.live.clone();
+    for (name, mark) in live {
+        let Some((line, column)) = file_position(mark.char_id, &parents_q, &children_q) else { continue };
+        if let Some(file_mark) = marks.file.get_mut(&name) {
+            file_mark.line = line;
+            file_mark.column = column;
+        }
+    }
+    marks.save();
+}
@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::keymap::Action;
+use crate::prompt::prompt_inactive;
+use crate::text_components::{line_characters, Character, Highlights};
+use crate::{AppState, Refocus};
+
+/// Clicks closer together than this on the same glyph count as one double or triple click
+const MULTI_CLICK: Duration = Duration::from_millis(400);
+const SELECTION_COLOR: Color = Color::rgba(0.3, 0.4, 0.7, 0.5);
+
+pub struct MousePlugin;
+
+impl Plugin for MousePlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (
+                (click_text, drag_select, clear_selection).run_if(prompt_inactive),
+                highlight_selection,
+            ).chain())
+            .init_resource::<Selection>();
+    }
+}
+
+/// Characters from `anchor` to `head` in document order, dragged over or clicked with the mouse
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub anchor: Option<Entity>,
+    pub head: Option<Entity>,
+    dragging: bool,
+}
+
+impl Selection {
+    /// Every selected character, in document order
+    pub fn characters(
+        &self,
+        parents_q: &Query<&Parent>,
+        children_q: &Query<&Children>,
+    ) -> Vec<Entity> {
+        let (Some(anchor), Some(head)) = (self.anchor, self.head) else { return vec![] };
+        if anchor == head { return vec![] }
+        let line_of = |char_id: Entity| parents_q
+            .get(char_id)
+            .and_then(|span_id| parents_q.get(**span_id))
+            .map(|line_id| **line_id)
+            .ok();
+        let (Some(anchor_line), Some(head_line)) = (line_of(anchor), line_of(head)) else { return vec![] };
+        let Ok(doc_id) = parents_q.get(anchor_line) else { return vec![] };
+        let Ok(lines) = children_q.get(**doc_id) else { return vec![] };
+        let (Some(anchor_index), Some(head_index)) = (
+            lines.iter().position(|id| *id == anchor_line),
+            lines.iter().position(|id| *id == head_line),
+        ) else { return vec![] };
+
+        let chars = lines[anchor_index.min(head_index)..=anchor_index.max(head_index)]
+            .iter()
+            .flat_map(|line_id| line_characters(*line_id, children_q))
+            .collect::<Vec<_>>();
+        let (Some(from), Some(to)) = (
+            chars.iter().position(|id| *id == anchor),
+            chars.iter().position(|id| *id == head),
+        ) else { return vec![] };
+        chars[from.min(to)..=from.max(to)].to_vec()
+    }
+}
+
+struct Clicks {
+    at: Duration,
+    char_id: Entity,
+    count: usize,
+}
+
+/// A click focuses the glyph, a double click selects its span and a triple click its line
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn click_text(
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut clicks: Local<Option<Clicks>>,
+    mut selection: ResMut<Selection>,
+    mut refocus_evw: EventWriter<Refocus>,
+    pressed_q: Query<(Entity, &Interaction), (Changed<Interaction>, With<Character>)>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+) {
+    if matches!(state.get(), AppState::Command | AppState::Find | AppState::Palette) { return }
+    for (char_id, interaction) in pressed_q.iter() {
+        if *interaction != Interaction::Pressed { continue }
+        let now = time.elapsed();
+        let count = match clicks.as_ref() {
+            Some(last) if last.char_id == char_id && now - last.at < MULTI_CLICK => last.count % 3 + 1,
+            _ => 1,
+        };
+        *clicks = Some(Clicks { at: now, char_id, count });
+
+        let Ok(span_id) = parents_q.get(char_id) else { continue };
+        let Ok(line_id) = parents_q.get(**span_id) else { continue };
+        let (target, selected) = match count {
+            1 => (char_id, vec![char_id]),
+            2 => (**span_id, children_q.get(**span_id).map(|chars| chars.to_vec()).unwrap_or_default()),
+            _ => (**line_id, line_characters(**line_id, &children_q)),
+        };
+        *selection = Selection {
+            anchor: selected.first().copied(),
+            head: selected.last().copied(),
+            dragging: count == 1,
+        };
+        refocus_evw.send(Refocus(target));
+        // only Travel mode moves around spans and lines
+        if count > 1 {
+            next_state.set(AppState::Travel);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn drag_select(
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut selection: ResMut<Selection>,
+    mut refocus_evw: EventWriter<Refocus>,
+    hovered_q: Query<(Entity, &Interaction), (Changed<Interaction>, With<Character>)>,
+) {
+    if !selection.dragging { return }
+    if !buttons.pressed(MouseButton::Left) {
+        // the cursor ends up where the drag let go
+        selection.dragging = false;
+        if let Some(head) = selection.head.filter(|head| selection.anchor != Some(*head)) {
+            refocus_evw.send(Refocus(head));
+        }
+        return
+    }
+    for (char_id, interaction) in hovered_q.iter() {
+        if *interaction == Interaction::Hovered {
+            selection.head = Some(char_id);
+        }
+    }
+}
+
+/// Typing anything drops the selection
+pub fn clear_selection(
+    mut action_evr: EventReader<Action>,
+    mut selection: ResMut<Selection>,
+) {
+    if action_evr.read().last().is_none() { return }
+    if selection.anchor.is_some() {
+        *selection = Selection::default();
+    }
+}
+
+fn highlight_selection(
+    selection: Res<Selection>,
+    mut highlighted: Local<Vec<Entity>>,
+    mut highlights_q: Query<&mut Highlights>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+) {
+    if !selection.is_changed() { return }
+    for char_id in highlighted.drain(..) {
+        if let Ok(mut highlights) = highlights_q.get_mut(char_id) {
+            highlights.selection = None;
+        }
+    }
+    for char_id in selection.characters(&parents_q, &children_q) {
+        if let Ok(mut highlights) = highlights_q.get_mut(char_id) {
+            highlights.selection = Some(SELECTION_COLOR);
+            highlighted.push(char_id);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use bevy::{prelude::*, window::RequestRedraw};
+
+use crate::cmdline::{CommandLine, ExCommand, StatusMessage, EX_COMMANDS};
+use crate::finder::fuzzy_score;
+use crate::keymap::{dispatch_keys, Action, Keymap, TypedText};
+use crate::prompt::prompt_inactive;
+use crate::repeat::RepeatChange;
+use crate::settings::Settings;
+use crate::AppState;
+
+const LISTED: usize = 20;
+
+pub struct PalettePlugin;
+
+impl Plugin for PalettePlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(OnEnter(AppState::Palette), open_palette)
+            .add_systems(Update, (
+                run_picked.before(dispatch_keys),
+                (
+                    control_palette
+                        .run_if(in_state(AppState::Palette).and_then(prompt_inactive))
+                        .after(dispatch_keys),
+                    show_palette,
+                ).chain(),
+            ))
+            .init_resource::<Palette>();
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PaletteEntry {
+    Action(Action),
+    /// Opens the command line with the command typed in, for its arguments
+    Command(&'static ExCommand),
+}
+
+impl PaletteEntry {
+    fn name(&self) -> String {
+        match self {
+            PaletteEntry::Action(action) => action.name(),
+            PaletteEntry::Command(command) => format!(":{} {}", command.name, command.args).trim_end().to_string(),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            PaletteEntry::Action(action) => action.description(),
+            PaletteEntry::Command(command) => command.description,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct Palette {
+    query: String,
+    /// Best match first
+    ranked: Vec<PaletteEntry>,
+    selected: usize,
+    return_to: AppState,
+    /// Runs once the palette has closed, so it applies to the mode it was opened from
+    picked: Option<PaletteEntry>,
+}
+
+impl Palette {
+    fn rank(&mut self) {
+        let actions = Action::ALL
+            .iter()
+            .filter(|action| action.in_palette())
+            .map(|action| PaletteEntry::Action(*action));
+        let commands = EX_COMMANDS.iter().map(PaletteEntry::Command);
+        let mut scored = actions
+            .chain(commands)
+            .filter_map(|entry| {
+                let text = format!("{} {}", entry.name(), entry.description());
+                fuzzy_score(&self.query, &text).map(|score| (score, entry))
+            })
+            .collect::<Vec<_>>();
+        // stable, so ties keep the order entries are declared in
+        scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        self.ranked = scored.into_iter().map(|(_, entry)| entry).collect();
+        self.selected = 0;
+    }
+}
+
+#[derive(Component)]
+struct PalettePanel;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        PalettePanel,
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(10.),
+                left: Val::Percent(20.),
+                right: Val::Percent(20.),
+                padding: UiRect::all(Val::Px(8.)),
+                overflow: Overflow::clip(),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.12, 0.12, 0.12)),
+            z_index: ZIndex::Global(9),
+            ..Default::default()
+        },
+    ));
+}
+
+fn open_palette(
+    mut transition_evr: EventReader<StateTransitionEvent<AppState>>,
+    mut palette: ResMut<Palette>,
+    mut status: ResMut<StatusMessage>,
+) {
+    if let Some(transition) = transition_evr.read().last() {
+        palette.return_to = transition.before;
+    }
+    palette.query.clear();
+    palette.picked = None;
+    palette.rank();
+    **status = None;
+}
+
+fn control_palette(
+    mut action_evr: EventReader<Action>,
+    mut text_evr: EventReader<TypedText>,
+    mut palette: ResMut<Palette>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+) {
+    let mut requery = false;
+    for TypedText(text) in text_evr.read() {
+        palette.query.push_str(text);
+        requery = true;
+    }
+    for action in action_evr.read() {
+        match action {
+            Action::Cancel => next_state.set(palette.return_to),
+            Action::Execute => {
+                palette.picked = palette.ranked.get(palette.selected).copied();
+                next_state.set(palette.return_to);
+                redraw_evw.send(RequestRedraw);
+            },
+            Action::Backspace => requery |= palette.query.pop().is_some(),
+            Action::SelectNext => {
+                palette.selected = (palette.selected + 1).min(palette.ranked.len().saturating_sub(1));
+            },
+            Action::SelectPrevious => palette.selected = palette.selected.saturating_sub(1),
+            _ => (),
+        }
+    }
+    if requery {
+        palette.rank();
+    }
+}
+
+fn run_picked(
+    state: Res<State<AppState>>,
+    mut palette: ResMut<Palette>,
+    mut command_line: ResMut<CommandLine>,
+    mut action_evw: EventWriter<Action>,
+    mut repeat_evw: EventWriter<RepeatChange>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+) {
+    if *state.get() == AppState::Palette { return }
+    match palette.picked.take() {
+        // turned into a change to repeat by the key dispatch, which the palette skips
+        Some(PaletteEntry::Action(Action::Repeat)) => { repeat_evw.send(RepeatChange(1)); },
+        Some(PaletteEntry::Action(action)) => { action_evw.send(action); },
+        Some(PaletteEntry::Command(command)) => {
+            let separator = if command.args.is_empty() { "" } else { " " };
+            command_line.prefill(format!("{}{separator}", command.name));
+            next_state.set(AppState::Command);
+            redraw_evw.send(RequestRedraw);
+        },
+        None => (),
+    }
+}
+
+fn show_palette(
+    state: Res<State<AppState>>,
+    palette: Res<Palette>,
+    keymap: Res<Keymap>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut panel_q: Query<(&mut Style, &mut Text), With<PalettePanel>>,
+) {
+    if !state.is_changed() && !palette.is_changed() { return }
+    let (mut panel_style, mut text) = panel_q.single_mut();
+    if *state.get() != AppState::Palette {
+        panel_style.display = Display::None;
+        return
+    }
+    panel_style.display = Display::Flex;
+
+    let font = match settings.font.as_str() {
+        "" => Handle::default(),
+        path => asset_server.load(path.to_string()),
+    };
+    let style = |alpha: f32| TextStyle {
+        font: font.clone(),
+        font_size: settings.font_size,
+        color: settings.foreground.with_a(alpha),
+    };
+    let first = palette.selected.saturating_sub(LISTED - 1);
+    let mut sections = vec![TextSection::new(format!("> {}\n", palette.query), style(1.))];
+    for (i, entry) in palette.ranked.iter().enumerate().skip(first).take(LISTED) {
+        let (marker, alpha) = if i == palette.selected { ("> ", 1.) } else { ("  ", 0.7) };
+        let bindings = match entry {
+            PaletteEntry::Action(action) => action
+                .bindings(&keymap)
+                .into_iter()
+                .map(|(state, keys)| format!("{}: {keys}", format!("{state:?}").to_lowercase()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            PaletteEntry::Command(_) => String::new(),
+        };
+        sections.push(TextSection::new(format!("{marker}{:<18}", entry.name()), style(alpha)));
+        sections.push(TextSection::new(entry.description(), style(alpha * 0.8)));
+        sections.push(TextSection::new(format!("  {bindings}\n"), style(alpha * 0.5)));
+    }
+    *text = Text::from_sections(sections);
+}
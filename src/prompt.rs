@@ -0,0 +1,106 @@
+use bevy::{ecs::event::ManualEventReader, input::keyboard::KeyboardInput, prelude::*};
+
+pub struct PromptPlugin;
+
+impl Plugin for PromptPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Startup, setup)
+            .add_systems(Update, (answer_prompt, show_prompt).chain())
+            .init_resource::<ActivePrompt>()
+            .add_event::<PromptAnswer>();
+    }
+}
+
+/// A question that blocks the editor until one of its choices is picked
+pub struct Prompt {
+    pub tag: &'static str,
+    pub message: String,
+    pub choices: Vec<(char, String)>,
+}
+
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct ActivePrompt(pub Option<Prompt>);
+
+#[derive(Event)]
+pub struct PromptAnswer {
+    pub tag: &'static str,
+    pub choice: char,
+}
+
+#[derive(Component)]
+struct PromptBar;
+
+pub fn prompt_inactive(prompt: Res<ActivePrompt>) -> bool {
+    prompt.is_none()
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        PromptBar,
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(0.),
+                left: Val::Px(0.),
+                right: Val::Px(0.),
+                padding: UiRect::all(Val::Px(4.)),
+                display: Display::None,
+                ..Default::default()
+            },
+            background_color: BackgroundColor::from(Color::rgb(0.3, 0.15, 0.1)),
+            z_index: ZIndex::Global(10),
+            ..Default::default()
+        },
+    ));
+}
+
+fn answer_prompt(
+    mut prompt: ResMut<ActivePrompt>,
+    mut char_events: ResMut<Events<ReceivedCharacter>>,
+    mut char_reader: Local<ManualEventReader<ReceivedCharacter>>,
+    mut key_events: ResMut<Events<KeyboardInput>>,
+    mut answer_evw: EventWriter<PromptAnswer>,
+) {
+    let Some(active) = prompt.0.as_ref() else {
+        char_reader.clear(&char_events);
+        return
+    };
+    let mut answer = None;
+    for char in char_reader.read(&char_events) {
+        let Some(ch) = char.char.chars().next() else { continue };
+        if active.choices.iter().any(|(key, _)| *key == ch) {
+            answer = Some(ch);
+            break
+        }
+    }
+    // keys typed into the prompt shouldn't leak into the mode that resumes afterwards
+    char_events.clear();
+    key_events.clear();
+    if let Some(choice) = answer {
+        answer_evw.send(PromptAnswer { tag: active.tag, choice });
+        **prompt = None;
+    }
+}
+
+fn show_prompt(
+    prompt: Res<ActivePrompt>,
+    mut bar_q: Query<(&mut Text, &mut Style), With<PromptBar>>,
+) {
+    if !prompt.is_changed() { return }
+    let (mut text, mut style) = bar_q.single_mut();
+    match prompt.0.as_ref() {
+        Some(active) => {
+            let choices = active.choices
+                .iter()
+                .map(|(key, label)| format!("[{key}] {label}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            *text = Text::from_section(
+                format!("{}  {}", active.message, choices),
+                Default::default(),
+            );
+            style.display = Display::Flex;
+        },
+        None => style.display = Display::None,
+    }
+}
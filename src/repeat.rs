@@ -0,0 +1,78 @@
+use bevy::prelude::*;
+
+use crate::indent::ShiftLine;
+use crate::{AppState, InsertChar};
+
+pub struct RepeatPlugin;
+
+impl Plugin for RepeatPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(OnEnter(AppState::Insert), start_insert_session)
+            .add_systems(OnExit(AppState::Insert), finish_insert_session)
+            .add_systems(Update, (capture_changes, repeat_change).chain())
+            .init_resource::<LastChange>()
+            .add_event::<RepeatChange>();
+    }
+}
+
+/// An edit `.` can apply again
+#[derive(Clone, Debug)]
+pub enum Change {
+    Shift(ShiftLine),
+    /// Everything typed between entering and leaving Insert mode
+    Insert(Vec<InsertChar>),
+}
+
+#[derive(Resource, Default)]
+pub struct LastChange {
+    change: Option<Change>,
+    session: Vec<InsertChar>,
+}
+
+/// `.`: applies the last change again, `count` times
+#[derive(Event, Clone, Copy)]
+pub struct RepeatChange(pub usize);
+
+fn start_insert_session(mut last: ResMut<LastChange>) {
+    last.session.clear();
+}
+
+fn finish_insert_session(mut last: ResMut<LastChange>) {
+    if last.session.is_empty() { return }
+    let session = std::mem::take(&mut last.session);
+    last.change = Some(Change::Insert(session));
+}
+
+fn capture_changes(
+    state: Res<State<AppState>>,
+    mut last: ResMut<LastChange>,
+    mut shift_evr: EventReader<ShiftLine>,
+    mut insert_evr: EventReader<InsertChar>,
+) {
+    // edits replayed by `.` come in outside of Insert mode and aren't captured again
+    for insert in insert_evr.read() {
+        if *state.get() == AppState::Insert {
+            last.session.push(insert.clone());
+        }
+    }
+    if let Some(shift) = shift_evr.read().last() {
+        last.change = Some(Change::Shift(*shift));
+    }
+}
+
+fn repeat_change(
+    mut repeat_evr: EventReader<RepeatChange>,
+    mut shift_evw: EventWriter<ShiftLine>,
+    mut insert_evw: EventWriter<InsertChar>,
+    last: Res<LastChange>,
+) {
+    for RepeatChange(count) in repeat_evr.read() {
+        let Some(change) = last.change.as_ref() else { continue };
+        for _ in 0..*count {
+            match change {
+                Change::Shift(shift) => { shift_evw.send(*shift); },
+                Change::Insert(inserts) => { insert_evw.send_batch(inserts.iter().cloned()); },
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::settings::Settings;
+use crate::text_components::{character_str, Character, Document, DocumentPath, Indent, Line, Span, SpanBundle};
+use crate::{process_insert, refocus, CurrentZipper, Refocus, ZipperFocus, ZipperType};
+
+pub struct SegmentPlugin;
+
+impl Plugin for SegmentPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, resegment_lines.after(process_insert).before(refocus));
+    }
+}
+
+/// Splits the text of a line after its indentation into the pieces that become its `Span`s
+pub trait SpanSegmenter: Send + Sync {
+    /// Consecutive pieces covering all of `text`
+    fn segments<'a>(&self, text: &'a str) -> Vec<&'a str>;
+}
+
+/// `foo.bar(baz) qux`: `foo.bar(baz) `, `qux`
+pub struct Whitespace;
+
+impl SpanSegmenter for Whitespace {
+    fn segments<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        text.split_inclusive([' ', '\t']).collect()
+    }
+}
+
+/// Unicode word boundaries: `don't stop.`: `don't `, `stop`, `.`
+pub struct WordBounds;
+
+impl SpanSegmenter for WordBounds {
+    fn segments<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        attach_blanks(text, text.split_word_bound_indices().map(|(start, _)| start))
+    }
+}
+
+/// Identifiers, numbers, operators, string literals and line comments:
+/// `foo.bar("a b") // c`: `foo`, `.`, `bar`, `(`, `"a b"`, `) `, `// c`
+pub struct CodeTokens;
+
+/// Operators kept together, longest first
+const OPERATORS: [&str; 19] = [
+    "..=", "...", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "..", "+=", "-=", "*=", "/=",
+    "<<", ">>", "|>",
+];
+
+impl SpanSegmenter for CodeTokens {
+    fn segments<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let run = |from: usize, keep: &dyn Fn(char) -> bool| text[from..]
+            .find(|c: char| !keep(c))
+            .map_or(text.len(), |len| from + len);
+        let mut starts = vec![];
+        let mut at = 0;
+        while let Some(c) = text[at..].chars().next() {
+            starts.push(at);
+            let rest = &text[at..];
+            let next = at + c.len_utf8();
+            at = match c {
+                c if is_word(c) => run(at, &is_word),
+                c if c.is_whitespace() => run(at, &char::is_whitespace),
+                '"' | '`' => string_end(text, at, c),
+                // a lifetime or label unless it's a char literal
+                '\'' if !is_char_literal(rest) && rest[1..].starts_with(is_word) => run(next, &is_word),
+                '\'' => string_end(text, at, c),
+                _ if rest.starts_with("//") => text.len(),
+                _ => OPERATORS
+                    .iter()
+                    .find(|op| rest.starts_with(*op))
+                    .map_or(next, |op| at + op.len()),
+            };
+        }
+        attach_blanks(text, starts.into_iter())
+    }
+}
+
+/// Where the string literal opened by `quote` at `start` ends, or the end of the line
+fn string_end(text: &str, start: usize, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in text[start..].char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return start + i + c.len_utf8(),
+            _ => (),
+        }
+    }
+    text.len()
+}
+
+/// `'x'` or `'\n'`, as opposed to `'a` or `'outer:`
+fn is_char_literal(rest: &str) -> bool {
+    let mut chars = rest.chars().skip(1);
+    match chars.next() {
+        Some('\\') => true,
+        Some(_) => chars.next() == Some('\''),
+        None => false,
+    }
+}
+
+/// Cuts `text` at `starts`, leaving whitespace on the end of the piece before it
+/// like the whitespace segmenter does
+fn attach_blanks(text: &str, starts: impl Iterator<Item = usize>) -> Vec<&str> {
+    let mut cuts = starts
+        .filter(|start| *start > 0 && !text[*start..].starts_with(char::is_whitespace))
+        .collect::<Vec<_>>();
+    cuts.push(text.len());
+    cuts.dedup();
+    let mut from = 0;
+    cuts.into_iter()
+        .map(|to| {
+            let piece = &text[from..to];
+            from = to;
+            piece
+        })
+        .filter(|piece| !piece.is_empty())
+        .collect()
+}
+
+/// The `segmenter` setting, `auto` picking one by file extension
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Segmenter {
+    #[default]
+    Auto,
+    Whitespace,
+    Words,
+    Code,
+}
+
+impl Segmenter {
+    pub fn resolve(self, path: &Path) -> &'static dyn SpanSegmenter {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match (self, extension) {
+            (Segmenter::Whitespace, _) => &Whitespace,
+            (Segmenter::Words, _) => &WordBounds,
+            (Segmenter::Code, _) => &CodeTokens,
+            (Segmenter::Auto, Some("md" | "markdown" | "txt")) => &WordBounds,
+            (
+                Segmenter::Auto,
+                Some(
+                    "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "go" | "java" | "js" | "jsx" | "ts"
+                    | "tsx" | "json" | "css" | "kt" | "swift" | "zig" | "py" | "rb" | "lua" | "sh" | "toml"
+                    | "yaml" | "yml"
+                ),
+            ) => &CodeTokens,
+            (Segmenter::Auto, _) => &Whitespace,
+        }
+    }
+}
+
+/// How a document's lines are cut into spans, `None` following the `segmenter` setting
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Segmentation(pub Option<Segmenter>);
+
+impl Segmentation {
+    pub fn get(&self, settings: &Settings) -> Segmenter {
+        self.unwrap_or(settings.segmenter)
+    }
+}
+
+/// Regroups the characters of edited lines into the spans their segmenter gives,
+/// reusing the spans already there. Every line is redone when the segmenter changes
+#[allow(clippy::too_many_arguments)]
+fn resegment_lines(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut refocus_events: ResMut<Events<Refocus>>,
+    doc_q: Query<(Ref<Segmentation>, &DocumentPath, &Children), With<Document>>,
+    edited_spans_q: Query<&Parent, (With<Span>, Changed<Children>)>,
+    edited_lines_q: Query<Entity, (With<Line>, Changed<Children>)>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    indent_q: Query<(), With<Indent>>,
+    text_q: Query<&Text, With<Character>>,
+) {
+    let mut lines = edited_spans_q
+        .iter()
+        .map(|line_id| **line_id)
+        .chain(edited_lines_q.iter())
+        .collect::<Vec<_>>();
+    for (segmentation, _, doc_lines) in doc_q.iter() {
+        if settings.is_changed() || segmentation.is_changed() {
+            lines.extend(doc_lines.iter().copied());
+        }
+    }
+    lines.sort();
+    lines.dedup();
+
+    let focus = curr_zipp_q.get_single().ok().map(|(zip_type, focus)| (*zip_type, **focus));
+    let mut refocus = None;
+    for line_id in lines {
+        let Ok((segmentation, path, _)) = parents_q.get(line_id).and_then(|doc_id| doc_q.get(**doc_id)) else { continue };
+        let segmenter = segmentation.get(&settings).resolve(path);
+        let Ok(spans) = children_q.get(line_id) else { continue };
+        let (indent, spans) = match spans.first() {
+            Some(first) if indent_q.contains(*first) => (Some(*first), &spans[1..]),
+            _ => (None, &spans[..]),
+        };
+        let current = spans
+            .iter()
+            .map(|span_id| children_q.get(*span_id).map_or(vec![], |chars| chars.to_vec()))
+            .collect::<Vec<_>>();
+        let chars = current
+            .iter()
+            .flatten()
+            .map(|char_id| (*char_id, text_q.get(*char_id).map(character_str).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        let text = chars.iter().map(|(_, grapheme)| grapheme.as_str()).collect::<String>();
+
+        let mut ends = segmenter
+            .segments(&text)
+            .into_iter()
+            .scan(0, |end, piece| {
+                *end += piece.len();
+                Some(*end)
+            })
+            .peekable();
+        let mut groups: Vec<Vec<Entity>> = vec![];
+        let mut offset = 0;
+        for (char_id, grapheme) in chars.iter() {
+            // a grapheme straddling a cut stays with the piece it starts in
+            let mut cut = false;
+            while ends.next_if(|end| *end <= offset).is_some() {
+                cut = true;
+            }
+            match groups.last_mut() {
+                Some(group) if !cut => group.push(*char_id),
+                _ => groups.push(vec![*char_id]),
+            }
+            offset += grapheme.len();
+        }
+        if groups == current { continue }
+
+        let mut new_spans = vec![];
+        for (i, group) in groups.iter().enumerate() {
+            let span_id = match spans.get(i) {
+                Some(span_id) => *span_id,
+                None => commands.spawn(SpanBundle::default()).id(),
+            };
+            commands.entity(span_id).replace_children(group);
+            new_spans.push(span_id);
+        }
+        for span_id in spans.iter().skip(groups.len()) {
+            commands.entity(*span_id).despawn_recursive();
+        }
+        let line_children = indent.into_iter().chain(new_spans.iter().copied()).collect::<Vec<_>>();
+        commands.entity(line_id).replace_children(&line_children);
+
+        // the zipper holds the old spans and siblings of a focus in here
+        let span_of = |char_id: Entity| groups
+            .iter()
+            .position(|group| group.contains(&char_id))
+            .map(|i| new_spans[i]);
+        refocus = match focus {
+            Some((ZipperType::Character, char_id)) if span_of(char_id).is_some() => Some(char_id),
+            Some((ZipperType::Span, span_id)) => current
+                .iter()
+                .zip(spans)
+                .find(|(_, old_id)| **old_id == span_id)
+                .and_then(|(old_chars, _)| old_chars.first())
+                .and_then(|char_id| span_of(*char_id))
+                .or(refocus),
+            _ => refocus,
+        };
+    }
+
+    // an edit that moves the focus already refocuses this frame
+    if refocus_events.iter_current_update_events().next().is_some() { return }
+    if let Some(target) = refocus {
+        refocus_events.send(Refocus(target));
+    }
+}
@@ -0,0 +1,357 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use anyhow::{bail, Context};
+use bevy::prelude::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::autopairs::AutoPairs;
+use crate::brackets::flag_mismatches;
+use crate::cmdline::StatusMessage;
+use crate::explorer::Explorer;
+use crate::fold::FoldMethod;
+use crate::indent::IndentSettings;
+use crate::marks::Mark;
+use crate::segment::{Segmentation, Segmenter};
+use crate::text_components::{AppWindow, Character, WorkingFilePath};
+use crate::wrap::Wrap;
+use crate::{AppState, CurrentFocus, CurrentZipper, ZipperFocus, ZipperType};
+
+const PROJECT_FILE: &str = ".diy-ed.toml";
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(PostStartup, load_settings)
+            .add_systems(Update, (
+                apply_settings.run_if(resource_changed::<Settings>),
+                // so a fresh unmatched bracket keeps its flag
+                style_characters.before(flag_mismatches),
+                set_local,
+            ))
+            .init_resource::<Settings>()
+            .add_event::<SetLocal>();
+    }
+}
+
+/// Everything `config.toml` and `:set` can change
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Mode the editor opens in
+    pub starting_mode: AppState,
+    /// Lines kept in view above and below the cursor
+    pub scrolloff: usize,
+    /// Columns kept in view left and right of the cursor when lines don't wrap
+    pub sidescrolloff: usize,
+    /// Soft wrap long lines instead of scrolling sideways, `:setlocal` overrides it per document
+    pub wrap: bool,
+    /// How lines are cut into spans: `auto`, `whitespace`, `words` or `code`,
+    /// `:setlocal` overrides it per document
+    pub segmenter: Segmenter,
+    /// How folds find their regions: `auto`, `indent`, `brackets` or `headings`
+    pub foldmethod: FoldMethod,
+    /// Pixels scrolled per line of mouse wheel
+    pub wheel_multiplier: f32,
+    /// Font file under `assets/`, empty for Bevy's built-in one
+    pub font: String,
+    pub font_size: f32,
+    pub background: HexColor,
+    pub foreground: HexColor,
+    pub cursor: HexColor,
+    /// Travel mode tints for the document, line and span around the focus
+    pub document_tint: HexColor,
+    pub line_tint: HexColor,
+    pub span_tint: HexColor,
+    /// Travel through the syntax of file types that have a parser, instead of lines
+    pub structure: bool,
+    /// Blink the caret in Normal and Insert mode
+    pub caret_blink: bool,
+    pub tabstop: usize,
+    pub expandtab: bool,
+    pub shiftwidth: usize,
+    pub softtabstop: usize,
+    /// Pairs like `"()"` per file extension, or `default`, replacing the built-in ones
+    pub auto_pairs: HashMap<String, Vec<String>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let indent = IndentSettings::default();
+        Self {
+            starting_mode: AppState::default(),
+            scrolloff: 3,
+            sidescrolloff: 5,
+            wrap: false,
+            segmenter: Segmenter::default(),
+            foldmethod: FoldMethod::default(),
+            wheel_multiplier: 20.,
+            font: String::new(),
+            font_size: TextStyle::default().font_size,
+            background: HexColor(Color::rgb(0.1, 0.1, 0.1)),
+            foreground: HexColor(Color::WHITE),
+            cursor: HexColor(Color::WHITE),
+            caret_blink: false,
+            structure: true,
+            document_tint: HexColor(Color::rgba(0.3, 0.5, 0.9, 0.06)),
+            line_tint: HexColor(Color::rgba(0.3, 0.8, 0.5, 0.1)),
+            span_tint: HexColor(Color::rgba(0.9, 0.7, 0.3, 0.14)),
+            tabstop: indent.tabstop,
+            expandtab: indent.expandtab,
+            shiftwidth: indent.shiftwidth,
+            softtabstop: indent.softtabstop,
+            auto_pairs: HashMap::new(),
+        }
+    }
+}
+
+/// A color written as `#rrggbb` or `#rrggbbaa`
+#[derive(Clone, Copy, PartialEq, Debug, Deref)]
+pub struct HexColor(pub Color);
+
+impl Serialize for HexColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = self.as_rgba_u8();
+        let hex = match a {
+            255 => format!("#{r:02x}{g:02x}{b:02x}"),
+            a => format!("#{r:02x}{g:02x}{b:02x}{a:02x}"),
+        };
+        serializer.serialize_str(&hex)
+    }
+}
+
+impl<'de> Deserialize<'de> for HexColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Color::hex(&hex)
+            .map(HexColor)
+            .map_err(|err| de::Error::custom(format!("{hex}: {err}")))
+    }
+}
+
+impl Settings {
+    /// `$XDG_CONFIG_HOME/diy-ed/config.toml`
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("diy-ed").join("config.toml"))
+    }
+
+    /// The closest `.diy-ed.toml` in the directories above `file`
+    pub fn project_path(file: &Path) -> Option<PathBuf> {
+        let file = file.canonicalize().ok()?;
+        file.ancestors()
+            .skip(1)
+            .map(|dir| dir.join(PROJECT_FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// The user config with the project's layered on top
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut table = toml::Table::new();
+        for path in paths {
+            let source = fs::read_to_string(path)?;
+            let layer: toml::Table = toml::from_str(&source)
+                .with_context(|| path.display().to_string())?;
+            table.extend(layer);
+        }
+        Ok(table.try_into()?)
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<toml::Value> {
+        let table = toml::Table::try_from(self)?;
+        match table.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => bail!("Unknown option: {name}"),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: toml::Value) -> anyhow::Result<()> {
+        let mut table = toml::Table::try_from(&*self)?;
+        if !table.contains_key(name) { bail!("Unknown option: {name}") }
+        table.insert(name.into(), value);
+        *self = table.try_into().with_context(|| format!("Invalid value for {name}"))?;
+        Ok(())
+    }
+
+    /// Runs the arguments of `:set`: `name=value`, `name?`, `name` and `noname`
+    /// for booleans. Returns what should be shown to the user
+    pub fn set_command(&mut self, args: &str) -> anyhow::Result<Option<String>> {
+        if args.is_empty() {
+            let table = toml::Table::try_from(&*self)?;
+            let all = table
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>();
+            return Ok(Some(all.join("  ")))
+        }
+        let mut shown = vec![];
+        for arg in args.split_whitespace() {
+            if let Some(name) = arg.strip_suffix('?') {
+                shown.push(format!("{name}={}", self.get(name)?));
+            } else if let Some((name, raw)) = arg.split_once('=') {
+                self.set(name, parse_value(raw))?;
+            } else if let Ok(toml::Value::Boolean(_)) = self.get(arg) {
+                self.set(arg, toml::Value::Boolean(true))?;
+            } else if let Some(Ok(toml::Value::Boolean(_))) = arg.strip_prefix("no").map(|name| self.get(name)) {
+                self.set(&arg[2..], toml::Value::Boolean(false))?;
+            } else {
+                shown.push(format!("{arg}={}", self.get(arg)?));
+            }
+        }
+        Ok((!shown.is_empty()).then(|| shown.join("  ")))
+    }
+}
+
+/// Reads a `:set` value as TOML, falling back to a bare string
+fn parse_value(raw: &str) -> toml::Value {
+    format!("value = {raw}")
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// `:setlocal`, options that only apply to the focused document
+#[derive(Event)]
+pub struct SetLocal(pub String);
+
+fn set_local(
+    settings: Res<Settings>,
+    mut status: ResMut<StatusMessage>,
+    mut setlocal_evr: EventReader<SetLocal>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    mut local_q: Query<(&mut Wrap, &mut Segmentation)>,
+) {
+    for SetLocal(args) in setlocal_evr.read() {
+        let document = Mark::focused(curr_zipp_q.single(), &parents_q, &children_q)
+            .and_then(|mark| parents_q.get(mark.line_id).ok());
+        let Some((mut wrap, mut segmentation)) = document.and_then(|doc_id| local_q.get_mut(**doc_id).ok()) else { continue };
+        let show_wrap = |wrap: &Wrap| format!("wrap={}", wrap.is_on(&settings));
+        let show_segmenter = |segmentation: &Segmentation| toml::Value::try_from(segmentation.get(&settings))
+            .map_or_else(|err| err.to_string(), |value| format!("segmenter={value}"));
+        let mut shown = vec![];
+        for arg in args.split_whitespace() {
+            match arg {
+                "wrap" => **wrap = Some(true),
+                "nowrap" => **wrap = Some(false),
+                // back to the global value
+                "wrap<" => **wrap = None,
+                "wrap?" => shown.push(show_wrap(&wrap)),
+                "segmenter<" => **segmentation = None,
+                "segmenter?" | "segmenter" => shown.push(show_segmenter(&segmentation)),
+                arg => match arg.strip_prefix("segmenter=").map(|raw| parse_value(raw).try_into::<Segmenter>()) {
+                    Some(Ok(segmenter)) => **segmentation = Some(segmenter),
+                    Some(Err(_)) => {
+                        shown.push(format!("Invalid value for segmenter: {}", &arg[10..]));
+                        break
+                    },
+                    None => {
+                        shown.push(format!("Not a local option: {arg}"));
+                        break
+                    },
+                },
+            }
+        }
+        if args.trim().is_empty() {
+            shown.push(show_wrap(&wrap));
+            shown.push(show_segmenter(&segmentation));
+        }
+        **status = (!shown.is_empty()).then(|| shown.join("  "));
+    }
+}
+
+fn load_settings(
+    file_path: Res<WorkingFilePath>,
+    mut settings: ResMut<Settings>,
+    mut status: ResMut<StatusMessage>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let paths = Settings::path()
+        .filter(|path| path.is_file())
+        .into_iter()
+        .chain(Settings::project_path(&file_path))
+        .collect::<Vec<_>>();
+    if !paths.is_empty() {
+        match Settings::load(&paths) {
+            Ok(loaded) => *settings = loaded,
+            Err(err) => **status = Some(format!("Couldn't load settings: {err:#}")),
+        }
+    }
+    next_state.set(settings.starting_mode);
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut indent: ResMut<IndentSettings>,
+    mut auto_pairs: ResMut<AutoPairs>,
+    mut window_q: Query<&mut BackgroundColor, (With<AppWindow>, Without<Explorer>)>,
+    mut outline_q: Query<&mut Outline, With<CurrentFocus>>,
+) {
+    indent.set_if_neq(IndentSettings {
+        tabstop: settings.tabstop,
+        expandtab: settings.expandtab,
+        shiftwidth: settings.shiftwidth,
+        softtabstop: settings.softtabstop,
+    });
+
+    let mut pairs = AutoPairs::default();
+    for (extension, list) in settings.auto_pairs.iter() {
+        let list = list
+            .iter()
+            .filter_map(|pair| {
+                let mut chars = pair.chars();
+                match (chars.next(), chars.next(), chars.next()) {
+                    (Some(open), Some(close), None) => Some((open.to_string(), close.to_string())),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        match extension.as_str() {
+            "default" => pairs.default = list,
+            extension => { pairs.by_extension.insert(extension.into(), list); },
+        }
+    }
+    *auto_pairs = pairs;
+
+    for mut background in window_q.iter_mut() {
+        *background = BackgroundColor(*settings.background);
+    }
+    for mut outline in outline_q.iter_mut() {
+        outline.color = *settings.cursor;
+    }
+}
+
+/// Gives new characters the configured font and color, and every character
+/// when one of those changes
+fn style_characters(
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut styled: Local<Option<(String, f32, Color)>>,
+    new_chars_q: Query<Entity, Added<Character>>,
+    mut text_q: Query<&mut Text, With<Character>>,
+) {
+    let current = (settings.font.clone(), settings.font_size, *settings.foreground);
+    let restyled = settings.is_changed() && styled.as_ref() != Some(&current);
+    if !restyled && new_chars_q.is_empty() { return }
+    *styled = Some(current);
+    let font = match settings.font.as_str() {
+        "" => Handle::default(),
+        path => asset_server.load(path.to_string()),
+    };
+    let style = TextStyle { font, font_size: settings.font_size, color: *settings.foreground };
+    let restyle = |text: &mut Text| {
+        for section in text.sections.iter_mut() {
+            section.style = style.clone();
+        }
+    };
+    if restyled {
+        text_q.iter_mut().for_each(|mut text| restyle(&mut text));
+    } else {
+        for char_id in new_chars_q.iter() {
+            if let Ok(mut text) = text_q.get_mut(char_id) {
+                restyle(&mut text);
+            }
+        }
+    }
+}
@@ -0,0 +1,624 @@
+use std::path::Path;
+
+use anyhow::Context;
+use bevy::prelude::*;
+use syn::{spanned::Spanned, visit::{self, Visit}};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::cmdline::StatusMessage;
+use crate::keymap::Action;
+use crate::mouse::{clear_selection, Selection};
+use crate::settings::Settings;
+use crate::text_components::{
+    character_str, line_characters, mark_modified, remove_characters, spawn_placeholder, Background, Character,
+    Document, DocumentPath, Highlights, Indent, Line, Modified, Span
+};
+use crate::{AppState, CurrentFocus, CurrentZipper, Refocus, ZipperFocus, ZipperType};
+
+const NODE_COLOR: Color = Color::rgba(0.9, 0.7, 0.3, 0.25);
+
+pub struct StructurePlugin;
+
+impl Plugin for StructurePlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(OnExit(AppState::Travel), leave_structure)
+            .add_systems(Update, (
+                delete_focus.run_if(in_state(AppState::Travel)).before(clear_selection),
+                build_structure,
+                highlight_node,
+            ).chain());
+    }
+}
+
+/// Part of a document found by a parser, from `start` up to `end` as
+/// (line, column) with columns counted in `char`s
+#[derive(Debug)]
+pub struct StructureOutline {
+    pub label: String,
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub children: Vec<StructureOutline>,
+}
+
+type Parser = fn(&str) -> anyhow::Result<Vec<StructureOutline>>;
+
+/// The parser for a file type, if it has one
+fn parser_for(path: &Path) -> Option<Parser> {
+    match path.extension()?.to_str()? {
+        "rs" => Some(rust_outline),
+        "md" | "markdown" => Some(markdown_outline),
+        "txt" => Some(text_outline),
+        _ => None,
+    }
+}
+
+/// Travel mode moves through this instead of the lines of a document whose
+/// file type has a parser. Points at the entity holding the top level nodes
+#[derive(Component)]
+pub struct Structure(Entity);
+
+/// The document a `Structure` belongs to
+#[derive(Component)]
+pub struct StructureRoot(Entity);
+
+/// A node of a document's `Structure`. Its children are smaller nodes, or
+/// for a leaf, the characters it covers
+#[derive(Component)]
+pub struct StructureNode {
+    pub label: String,
+    pub chars: Vec<Entity>,
+}
+
+/// What the zipper moves into from `focus` while traveling a structured document
+pub fn structure_children(
+    focus: Entity,
+    structure_q: &Query<&Structure>,
+    node_q: &Query<&StructureNode>,
+    children_q: &Query<&Children>,
+) -> Option<(ZipperType, Vec<Entity>)> {
+    if let Ok(Structure(root)) = structure_q.get(focus) {
+        let nodes = children_q.get(*root).ok()?;
+        return Some((ZipperType::Structure, nodes.to_vec()))
+    }
+    let node = node_q.get(focus).ok()?;
+    match children_q.get(focus) {
+        Ok(nodes) => Some((ZipperType::Structure, nodes.to_vec())),
+        Err(_) => Some((ZipperType::Character, node.chars.clone())),
+    }
+}
+
+/// The structured document above `target` and the child indices leading from
+/// it down to `target`. Lines, spans and characters lead to the deepest node
+/// around their first character, then that character
+pub fn structure_path(
+    target: Entity,
+    structure_q: &Query<&Structure>,
+    root_q: &Query<&StructureRoot>,
+    node_q: &Query<&StructureNode>,
+    parents_q: &Query<&Parent>,
+    children_q: &Query<&Children>,
+) -> Option<(Entity, Vec<usize>)> {
+    let index_in = |parent: Entity, child: Entity| children_q
+        .get(parent)
+        .ok()
+        .and_then(|children| children.iter().position(|id| *id == child));
+
+    if node_q.contains(target) {
+        let mut path = vec![];
+        let mut node = target;
+        while let Ok(parent) = parents_q.get(node) {
+            path.push(index_in(**parent, node)?);
+            node = **parent;
+        }
+        path.reverse();
+        let StructureRoot(document) = root_q.get(node).ok()?;
+        return Some((*document, path))
+    }
+
+    let document = parents_q
+        .iter_ancestors(target)
+        .find(|ancestor| structure_q.contains(*ancestor))?;
+    let mut char_id = target;
+    while let Some(first) = children_q.get(char_id).ok().and_then(|children| children.first()) {
+        char_id = *first;
+    }
+    let Structure(root) = structure_q.get(document).ok()?;
+    let mut path = vec![];
+    let mut node = *root;
+    loop {
+        let Ok(children) = children_q.get(node) else {
+            if let Ok(leaf) = node_q.get(node) {
+                path.extend(leaf.chars.iter().position(|id| *id == char_id));
+            }
+            break
+        };
+        let Some((index, child)) = children
+            .iter()
+            .enumerate()
+            .find(|(_, id)| node_q.get(**id).is_ok_and(|child| child.chars.contains(&char_id)))
+        else { break };
+        path.push(index);
+        node = *child;
+    }
+    Some((document, path))
+}
+
+/// Builds items, blocks, statements and expressions, skipping nodes that cover
+/// exactly what their parent does
+struct RustOutliner {
+    stack: Vec<StructureOutline>,
+}
+
+impl RustOutliner {
+    fn node(&mut self, label: String, span: proc_macro2::Span, visit: impl FnOnce(&mut Self)) {
+        let (start, end) = (span.start(), span.end());
+        let (start, end) = ((start.line - 1, start.column), (end.line - 1, end.column));
+        let parent = self.stack.last().expect("the root stays on the stack");
+        if parent.start == start && parent.end == end {
+            return visit(self)
+        }
+        self.stack.push(StructureOutline { label, start, end, children: vec![] });
+        visit(self);
+        let node = self.stack.pop().expect("pushed above");
+        self.stack.last_mut().expect("the root stays on the stack").children.push(node);
+    }
+}
+
+fn item_label(item: &syn::Item) -> String {
+    match item {
+        syn::Item::Fn(item) => format!("fn {}", item.sig.ident),
+        syn::Item::Struct(item) => format!("struct {}", item.ident),
+        syn::Item::Enum(item) => format!("enum {}", item.ident),
+        syn::Item::Trait(item) => format!("trait {}", item.ident),
+        syn::Item::Mod(item) => format!("mod {}", item.ident),
+        syn::Item::Const(item) => format!("const {}", item.ident),
+        syn::Item::Static(item) => format!("static {}", item.ident),
+        syn::Item::Type(item) => format!("type {}", item.ident),
+        syn::Item::Impl(_) => "impl".into(),
+        syn::Item::Use(_) => "use".into(),
+        _ => "item".into(),
+    }
+}
+
+impl<'ast> Visit<'ast> for RustOutliner {
+    fn visit_item(&mut self, item: &'ast syn::Item) {
+        self.node(item_label(item), item.span(), |this| visit::visit_item(this, item));
+    }
+
+    fn visit_impl_item(&mut self, item: &'ast syn::ImplItem) {
+        let label = match item {
+            syn::ImplItem::Fn(item) => format!("fn {}", item.sig.ident),
+            syn::ImplItem::Const(item) => format!("const {}", item.ident),
+            syn::ImplItem::Type(item) => format!("type {}", item.ident),
+            _ => "item".into(),
+        };
+        self.node(label, item.span(), |this| visit::visit_impl_item(this, item));
+    }
+
+    fn visit_trait_item(&mut self, item: &'ast syn::TraitItem) {
+        let label = match item {
+            syn::TraitItem::Fn(item) => format!("fn {}", item.sig.ident),
+            _ => "item".into(),
+        };
+        self.node(label, item.span(), |this| visit::visit_trait_item(this, item));
+    }
+
+    fn visit_block(&mut self, block: &'ast syn::Block) {
+        self.node("block".into(), block.span(), |this| visit::visit_block(this, block));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast syn::Stmt) {
+        self.node("statement".into(), stmt.span(), |this| visit::visit_stmt(this, stmt));
+    }
+
+    fn visit_expr(&mut self, expr: &'ast syn::Expr) {
+        self.node("expression".into(), expr.span(), |this| visit::visit_expr(this, expr));
+    }
+}
+
+fn rust_outline(source: &str) -> anyhow::Result<Vec<StructureOutline>> {
+    let file = syn::parse_file(source).context("not valid Rust")?;
+    let root = StructureOutline { label: String::new(), start: (0, 0), end: (usize::MAX, 0), children: vec![] };
+    let mut outliner = RustOutliner { stack: vec![root] };
+    outliner.visit_file(&file);
+    Ok(outliner.stack.pop().map(|root| root.children).unwrap_or_default())
+}
+
+/// (line, column) of byte offsets into a source
+struct Positions<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Positions<'a> {
+    fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Self { source, line_starts }
+    }
+
+    fn at(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        (line, self.source[self.line_starts[line]..offset].chars().count())
+    }
+
+    fn outline(&self, label: String, start: usize, end: usize, children: Vec<StructureOutline>) -> StructureOutline {
+        StructureOutline { label, start: self.at(start), end: self.at(end), children }
+    }
+}
+
+/// `# Title` and the like, with its level
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|ch| *ch == '#').count();
+    let title = line[level..].strip_prefix(' ')?;
+    (1..=6).contains(&level).then_some((level, title.trim()))
+}
+
+/// A paragraph's sentences and their words
+fn paragraph(positions: &Positions, label: &str, start: usize, end: usize) -> StructureOutline {
+    // a sentence can carry on to the next line
+    let text = positions.source[start..end].replace('\n', " ");
+    let sentences = text
+        .split_sentence_bound_indices()
+        .filter_map(|(offset, sentence)| {
+            let sentence = sentence.trim_end();
+            if sentence.is_empty() { return None }
+            let words = sentence
+                .split_word_bound_indices()
+                // punctuation gets a node of its own, only the space between is skipped
+                .filter(|(_, word)| !word.trim().is_empty())
+                .map(|(at, word)| {
+                    let at = start + offset + at;
+                    positions.outline(word.into(), at, at + word.len(), vec![])
+                })
+                .collect();
+            let at = start + offset;
+            Some(positions.outline("sentence".into(), at, at + sentence.len(), words))
+        })
+        .collect();
+    positions.outline(label.into(), start, end, sentences)
+}
+
+/// Paragraphs are runs of lines without a blank one between them. With
+/// `headings`, each Markdown heading starts a section running up to the next
+/// one at the same level or above
+fn prose_outline(source: &str, headings: bool) -> Vec<StructureOutline> {
+    let positions = Positions::new(source);
+    // open sections with their level, the document itself at the bottom
+    let mut sections = vec![(0, positions.outline(String::new(), 0, 0, vec![]))];
+    let mut block: Option<(usize, usize)> = None;
+    let mut blocks = vec![];
+    for (line_start, line) in positions.line_starts.iter().zip(source.split('\n')) {
+        let line_end = line_start + line.len();
+        let title = headings.then(|| heading(line)).flatten();
+        if line.trim().is_empty() || title.is_some() {
+            blocks.extend(block.take());
+        }
+        match title {
+            Some(_) => blocks.push((*line_start, line_end)),
+            None if line.trim().is_empty() => (),
+            None => block = Some((block.map_or(*line_start, |(start, _)| start), line_end)),
+        }
+    }
+    blocks.extend(block);
+
+    for (start, end) in blocks {
+        let line = &source[start..end];
+        if let Some((level, title)) = headings.then(|| heading(line)).flatten() {
+            while sections.len() > 1 && sections.last().is_some_and(|(open, _)| *open >= level) {
+                let (_, section) = sections.pop().expect("more than one open");
+                sections.last_mut().expect("the document stays open").1.children.push(section);
+            }
+            sections.push((level, positions.outline(format!("section {title}"), start, end, vec![])));
+        }
+        let label = match headings && heading(line).is_some() {
+            true => "heading",
+            false => "paragraph",
+        };
+        let end_at = positions.at(end);
+        for (level, section) in sections.iter_mut() {
+            if *level > 0 {
+                section.end = end_at;
+            }
+        }
+        sections.last_mut().expect("the document stays open").1.children.push(paragraph(&positions, label, start, end));
+    }
+    while sections.len() > 1 {
+        let (_, section) = sections.pop().expect("more than one open");
+        sections.last_mut().expect("the document stays open").1.children.push(section);
+    }
+    sections.pop().map(|(_, document)| document.children).unwrap_or_default()
+}
+
+fn markdown_outline(source: &str) -> anyhow::Result<Vec<StructureOutline>> {
+    Ok(prose_outline(source, true))
+}
+
+fn text_outline(source: &str) -> anyhow::Result<Vec<StructureOutline>> {
+    Ok(prose_outline(source, false))
+}
+
+/// Each line's characters with the column they start at
+fn char_columns(
+    lines: &Children,
+    children_q: &Query<&Children>,
+    text_q: &Query<&Text, With<Character>>,
+) -> (String, Vec<Vec<(usize, Entity)>>) {
+    let mut source = String::new();
+    let mut columns = vec![];
+    for (i, line_id) in lines.iter().enumerate() {
+        if i > 0 { source.push('\n') }
+        let mut col = 0;
+        let mut line = vec![];
+        for char_id in line_characters(*line_id, children_q) {
+            let str = text_q.get(char_id).map(character_str).unwrap_or_default();
+            line.push((col, char_id));
+            col += str.chars().count();
+            source.push_str(&str);
+        }
+        columns.push(line);
+    }
+    (source, columns)
+}
+
+/// Spawns the nodes of an outline that cover any characters. `replacing` is
+/// the first and last character of a node whose match ends up in `found`
+fn spawn_outline(
+    commands: &mut Commands,
+    outline: &StructureOutline,
+    columns: &[Vec<(usize, Entity)>],
+    replacing: Option<(Entity, Entity)>,
+    found: &mut Option<Entity>,
+) -> Option<Entity> {
+    let last_line = outline.end.0.min(columns.len().checked_sub(1)?);
+    let chars = (outline.start.0..=last_line)
+        .flat_map(|line| columns[line].iter().map(move |(col, id)| (line, *col, *id)))
+        .filter(|(line, col, _)| (*line, *col) >= outline.start && (*line, *col) < outline.end)
+        .map(|(_, _, id)| id)
+        .collect::<Vec<_>>();
+    if chars.is_empty() { return None }
+    let children = outline.children
+        .iter()
+        .filter_map(|child| spawn_outline(commands, child, columns, replacing, found))
+        .collect::<Vec<_>>();
+    let ends = chars.first().copied().zip(chars.last().copied());
+    let node = commands.spawn(StructureNode { label: outline.label.clone(), chars }).id();
+    commands.entity(node).push_children(&children);
+    if found.is_none() && ends.is_some() && ends == replacing {
+        *found = Some(node);
+    }
+    Some(node)
+}
+
+/// Parses the shown document when Travel mode starts, when it's switched to and
+/// after edits made in it, then puts the zipper back on what it was focused on
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn build_structure(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    settings: Res<Settings>,
+    mut status: ResMut<StatusMessage>,
+    mut refocus_evw: EventWriter<Refocus>,
+    // characters come and go through the children of their span, and spans through their line's
+    edited_spans_q: Query<&Parent, (With<Span>, Changed<Children>)>,
+    edited_lines_q: Query<&Parent, (With<Line>, Changed<Children>)>,
+    edited_docs_q: Query<(), (With<Document>, Changed<Children>)>,
+    // buffers are shown by moving them into the window
+    doc_q: Query<
+        (Entity, Ref<Parent>, &DocumentPath, &Children, Option<&Structure>),
+        (With<Document>, Without<Background>),
+    >,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    text_q: Query<&Text, With<Character>>,
+    node_q: Query<&StructureNode>,
+    curr_zipp_q: Query<&ZipperFocus, With<CurrentZipper>>,
+    alive_q: Query<()>,
+) {
+    if *state.get() != AppState::Travel { return }
+    let Ok((document, parent, path, lines, structure)) = doc_q.get_single() else { return };
+    let edited = edited_docs_q.contains(document)
+        || edited_lines_q.iter().any(|doc_id| **doc_id == document)
+        || edited_spans_q.iter().any(|line_id| parents_q.get(**line_id).is_ok_and(|doc_id| **doc_id == document));
+    let entered = state.is_changed();
+    let reparse = entered || edited || settings.is_changed();
+    if !reparse && !parent.is_changed() { return }
+
+    // a node's replacement is the one covering the same characters
+    let focus = curr_zipp_q.get_single().ok().map(|focus| **focus);
+    let old_node = focus
+        .and_then(|focus| node_q.get(focus).ok())
+        .and_then(|node| Some((*node.chars.first()?, *node.chars.last()?)));
+    let mut replacement = None;
+
+    if let Some(Structure(root)) = structure {
+        commands.entity(*root).despawn_recursive();
+        commands.entity(document).remove::<Structure>();
+    }
+    let outlines = match parser_for(path).filter(|_| settings.structure) {
+        Some(parser) => {
+            let (source, columns) = char_columns(lines, &children_q, &text_q);
+            match parser(&source) {
+                Ok(outlines) => Some((outlines, columns)),
+                Err(err) => {
+                    if entered {
+                        **status = Some(format!("{err:#}, traveling by lines instead"));
+                    }
+                    None
+                },
+            }
+        },
+        None => None,
+    };
+    if let Some((outlines, columns)) = outlines {
+        let nodes = outlines
+            .iter()
+            .filter_map(|outline| spawn_outline(&mut commands, outline, &columns, old_node, &mut replacement))
+            .collect::<Vec<_>>();
+        if !nodes.is_empty() {
+            let root = commands.spawn(StructureRoot(document)).push_children(&nodes).id();
+            commands.entity(document).insert(Structure(root));
+        }
+    }
+
+    // a buffer that was just switched to is focused by whatever opened it
+    if !reparse { return }
+    // the zipper still points into the old nodes
+    let target = match old_node {
+        Some((first, _)) => replacement.or(Some(first)),
+        None => focus,
+    };
+    // a deleted focus was already moved off of
+    let target = target.filter(|target| replacement == Some(*target) || alive_q.contains(*target));
+    if let Some(target) = target {
+        refocus_evw.send(Refocus(target));
+    }
+}
+
+/// Normal and Insert mode move through lines again, so the zipper is rebuilt
+/// on the character it was on, or the first one of the node
+fn leave_structure(
+    mut refocus_evw: EventWriter<Refocus>,
+    zippers_q: Query<(&ZipperFocus, Has<CurrentZipper>)>,
+    node_q: Query<&StructureNode>,
+) {
+    if !zippers_q.iter().any(|(focus, _)| node_q.contains(**focus)) { return }
+    let Some((focus, _)) = zippers_q.iter().find(|(_, current)| *current) else { return };
+    let target = match node_q.get(**focus) {
+        Ok(node) => node.chars.first().copied(),
+        Err(_) => Some(**focus),
+    };
+    if let Some(target) = target {
+        refocus_evw.send(Refocus(target));
+    }
+}
+
+/// Tints the characters of a focused node, which has nothing on screen to outline
+fn highlight_node(
+    mut highlighted: Local<Vec<Entity>>,
+    new_focus_q: Query<(), Added<CurrentFocus>>,
+    focus_q: Query<&StructureNode, With<CurrentFocus>>,
+    mut highlights_q: Query<&mut Highlights>,
+) {
+    if new_focus_q.is_empty() { return }
+    for char_id in highlighted.drain(..) {
+        if let Ok(mut highlights) = highlights_q.get_mut(char_id) {
+            highlights.node = None;
+        }
+    }
+    let Ok(node) = focus_q.get_single() else { return };
+    for char_id in node.chars.iter() {
+        if let Ok(mut highlights) = highlights_q.get_mut(*char_id) {
+            highlights.node = Some(NODE_COLOR);
+            highlighted.push(*char_id);
+        }
+    }
+}
+
+/// `x` in Travel mode: deletes the mouse selection, or else the focused line, span,
+/// character or node. Lines it empties go with it
+#[allow(clippy::too_many_arguments)]
+fn delete_focus(
+    mut commands: Commands,
+    mut action_evr: EventReader<Action>,
+    mut refocus_evw: EventWriter<Refocus>,
+    selection: Res<Selection>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    node_q: Query<&StructureNode>,
+    text_q: Query<&Text, With<Character>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    mut modified_q: Query<&mut Modified>,
+) {
+    if !action_evr.read().any(|action| *action == Action::DeleteFocus) { return }
+    let (zip_type, focus) = curr_zipp_q.single();
+    let selected = selection.characters(&parents_q, &children_q);
+    let chars = match zip_type {
+        // the explorer deletes files rather than text
+        ZipperType::Window | ZipperType::Document | ZipperType::Entry => return,
+        _ if !selected.is_empty() => selected,
+        ZipperType::Structure => node_q.get(**focus).map(|node| node.chars.clone()).unwrap_or_default(),
+        ZipperType::Line => line_characters(**focus, &children_q),
+        ZipperType::Span => children_q.get(**focus).map(|chars| chars.to_vec()).unwrap_or_default(),
+        ZipperType::Character => vec![**focus],
+    };
+    let line_of = |char_id: &Entity| parents_q
+        .get(*char_id)
+        .and_then(|span_id| parents_q.get(**span_id))
+        .map(|line_id| **line_id)
+        .ok();
+    let (Some(first_line), Some(last_line)) = (
+        chars.first().and_then(line_of),
+        chars.last().and_then(line_of),
+    ) else { return };
+    let Ok(doc_id) = parents_q.get(first_line) else { return };
+    let Ok(lines) = children_q.get(**doc_id) else { return };
+    let (Some(first), Some(last)) = (
+        lines.iter().position(|id| *id == first_line),
+        lines.iter().position(|id| *id == last_line),
+    ) else { return };
+    mark_modified(**doc_id, &parents_q, &mut modified_q);
+
+    let first_chars = line_characters(first_line, &children_q);
+    let last_chars = line_characters(last_line, &children_q);
+    let before = &first_chars[..first_chars.iter().position(|id| *id == chars[0]).unwrap_or(0)];
+    let after = last_chars
+        .iter()
+        .position(|id| Some(id) == chars.last())
+        .map_or(&[][..], |index| &last_chars[index + 1..]);
+    let is_blank = |ids: &[Entity]| ids
+        .iter()
+        .all(|id| text_q.get(*id).map(character_str).unwrap_or_default().trim().is_empty());
+
+    let next = if is_blank(before) && is_blank(after) {
+        // nothing but indentation would be left, so the lines go whole
+        if first == 0 && last == lines.len() - 1 {
+            for line_id in lines[1..].iter() {
+                commands.entity(*line_id).despawn_recursive();
+            }
+            commands.entity(first_line).despawn_descendants();
+            Some(spawn_placeholder(&mut commands, first_line))
+        } else {
+            for line_id in lines[first..=last].iter() {
+                commands.entity(*line_id).despawn_recursive();
+            }
+            lines
+                .get(last + 1)
+                .or(first.checked_sub(1).and_then(|index| lines.get(index)))
+                .and_then(|line_id| line_characters(*line_id, &children_q).first().copied())
+        }
+    } else {
+        let ends = chars
+            .iter()
+            .filter(|id| line_of(id).is_some_and(|line_id| line_id == first_line || line_id == last_line))
+            .copied()
+            .collect::<Vec<_>>();
+        remove_characters(&mut commands, &ends, &parents_q, &children_q);
+        for line_id in lines[first + 1..last.max(first + 1)].iter() {
+            commands.entity(*line_id).despawn_recursive();
+        }
+        if first != last {
+            // what's left of the last line joins the first
+            let kept = children_q
+                .get(last_line)
+                .map(|spans| spans
+                    .iter()
+                    .filter(|span_id| children_q
+                        .get(**span_id)
+                        .is_ok_and(|span_chars| span_chars.iter().any(|id| !ends.contains(id))))
+                    .copied()
+                    .collect::<Vec<_>>())
+                .unwrap_or_default();
+            for span_id in kept.iter() {
+                commands.entity(*span_id).remove::<Indent>();
+            }
+            commands.entity(first_line).push_children(&kept);
+            commands.entity(last_line).despawn_recursive();
+        }
+        after.first().or(before.last()).copied()
+    };
+    if let Some(next) = next {
+        refocus_evw.send(Refocus(next));
+    }
+}
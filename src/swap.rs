@@ -0,0 +1,140 @@
+use std::{collections::VecDeque, fs, path::{Path, PathBuf}, time::Duration};
+
+use bevy::{app::AppExit, prelude::*, time::common_conditions::on_timer};
+
+use crate::prompt::{ActivePrompt, Prompt, PromptAnswer};
+use crate::text_components::{
+    document_text, Character, Document, DocumentPath, Line, Modified, ReplaceContent, Span
+};
+
+const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+const RECOVER_PROMPT: &str = "swap-recover";
+
+pub struct SwapPlugin;
+
+impl Plugin for SwapPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (
+                write_swap.run_if(on_timer(SWAP_INTERVAL)),
+                remove_swap_when_clean,
+                // answered first, so the next buffer's question isn't asked twice
+                (recover, offer_recovery).chain(),
+            ))
+            .add_systems(Last, remove_swap_on_exit)
+            .init_resource::<PendingRecovery>();
+    }
+}
+
+/// A swap file left behind by a session that didn't exit cleanly
+struct Recovery {
+    document: Entity,
+    swap: PathBuf,
+    content: String,
+}
+
+/// Recoveries still to ask about, one prompt at a time, the one being asked first
+#[derive(Resource, Default, Deref, DerefMut)]
+struct PendingRecovery(VecDeque<Recovery>);
+
+/// `dir/file.rs` is journaled to `dir/.file.rs.swp`
+pub fn swap_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.swp"))
+}
+
+/// Looks for a swap file next to every buffer as it's opened
+fn offer_recovery(
+    doc_q: Query<(Entity, &DocumentPath), Added<Document>>,
+    mut pending: ResMut<PendingRecovery>,
+    mut prompt: ResMut<ActivePrompt>,
+) {
+    for (document, path) in doc_q.iter() {
+        let swap = swap_path(path);
+        let Ok(content) = fs::read_to_string(&swap) else { continue };
+        pending.push_back(Recovery { document, swap, content });
+    }
+    if prompt.is_some() { return }
+    let Some(recovery) = pending.front() else { return };
+    **prompt = Some(Prompt {
+        tag: RECOVER_PROMPT,
+        message: format!("Found unsaved changes in {}.", recovery.swap.display()),
+        choices: vec![
+            ('r', "recover".into()),
+            ('d', "delete swap".into()),
+            ('i', "ignore".into()),
+        ],
+    });
+}
+
+fn recover(
+    mut answer_evr: EventReader<PromptAnswer>,
+    mut replace_evw: EventWriter<ReplaceContent>,
+    mut pending: ResMut<PendingRecovery>,
+    mut doc_q: Query<&mut Modified, With<Document>>,
+) {
+    for answer in answer_evr.read() {
+        if answer.tag != RECOVER_PROMPT { continue }
+        let Some(Recovery { document, swap, content }) = pending.pop_front() else { continue };
+        match answer.choice {
+            'r' => {
+                let Ok(mut modified) = doc_q.get_mut(document) else { continue };
+                replace_evw.send(ReplaceContent { document, content });
+                **modified = true;
+            },
+            'd' => {
+                if let Err(err) = fs::remove_file(swap) {
+                    error!("couldn't remove swap file: {err}");
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Journals every modified buffer, shown or not
+#[allow(clippy::type_complexity)]
+fn write_swap(
+    doc_q: Query<(&DocumentPath, &Children, Ref<Modified>), With<Document>>,
+    content_q: Query<&Children, Or<(With<Line>, With<Span>)>>,
+    text_q: Query<&Text, With<Character>>,
+) {
+    for (path, doc_children, modified) in doc_q.iter() {
+        if !modified.is_changed() || !**modified { continue }
+        let output = document_text(doc_children, &content_q, &text_q);
+        if let Err(err) = fs::write(swap_path(path), output) {
+            error!("couldn't write swap file: {err}");
+        }
+    }
+}
+
+fn remove_swap_when_clean(doc_q: Query<(&DocumentPath, Ref<Modified>), With<Document>>) {
+    for (path, modified) in doc_q.iter() {
+        if modified.is_added() || !modified.is_changed() || **modified { continue }
+        let swap = swap_path(path);
+        if swap.exists() {
+            if let Err(err) = fs::remove_file(swap) {
+                error!("couldn't remove swap file: {err}");
+            }
+        }
+    }
+}
+
+fn remove_swap_on_exit(
+    mut exit_evr: EventReader<AppExit>,
+    pending: Res<PendingRecovery>,
+    doc_q: Query<(&DocumentPath, &Modified), With<Document>>,
+) {
+    if exit_evr.read().last().is_none() { return }
+    for (path, modified) in doc_q.iter() {
+        if **modified { continue }
+        let swap = swap_path(path);
+        // an unanswered recovery prompt still owns the swap file
+        if pending.iter().any(|recovery| recovery.swap == swap) { continue }
+        if swap.exists() {
+            let _ = fs::remove_file(swap);
+        }
+    }
+}
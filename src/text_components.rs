@@ -1,22 +1,42 @@
-use std::{fs, path::PathBuf};
+use std::{fs, path::{Path, PathBuf}};
 
-use bevy::{input::mouse::{MouseScrollUnit, MouseWheel}, prelude::*};
+use bevy::{ecs::query::QueryFilter, input::mouse::{MouseScrollUnit, MouseWheel}, prelude::*, window::RequestRedraw};
 
 use clap::Parser;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cmdline::StatusMessage;
+use crate::explorer::Explorer;
+use crate::file_watch::{DiskStamp, FileStamp};
+use crate::marks::LongJump;
+use crate::segment::{Segmentation, SpanSegmenter};
+use crate::settings::Settings;
+use crate::wrap::Wrap;
+use crate::{refocus, AppState, Refocus};
 
 pub struct DocumentPlugin;
 
 impl Plugin for DocumentPlugin {
     fn build(&self, appl: &mut App) {
         appl.add_systems(Startup, setup)
-            .add_systems(Update, (mouse_scroll, scroll))
+            .add_systems(Update, (
+                mouse_scroll,
+                scroll,
+                replace_content,
+                renumber_lines,
+                open_file.before(refocus),
+            ))
+            .add_systems(PostUpdate, paint_highlights)
             .init_resource::<WorkingFilePath>()
-            .add_event::<Scroll>();
+            .add_event::<OpenFile>()
+            .add_event::<Scroll>()
+            .add_event::<ReplaceContent>();
     }
 }
 
 #[derive(Parser, Debug)]
-struct CLI { path: Option<PathBuf> }
+struct Cli { path: Option<PathBuf> }
 
 #[derive(Component, Default)]
 pub enum SplitDir {
@@ -29,6 +49,18 @@ pub enum SplitDir {
 #[derive(Component, Default, Reflect)]
 pub struct Document;
 
+/// The file a `Document` was read from
+#[derive(Component, Deref, Reflect)]
+pub struct DocumentPath(pub PathBuf);
+
+/// A buffer that isn't shown, parked under the `BufferList` until it's opened again
+#[derive(Component, Reflect)]
+pub struct Background;
+
+/// Hidden node holding the `Background` documents, so the window only ever has one
+#[derive(Component)]
+pub struct BufferList;
+
 #[derive(Component, Default, Reflect)]
 pub struct AppWindow;
 
@@ -41,12 +73,47 @@ pub struct Line;
 #[derive(Component, Reflect)]
 pub struct Span;
 
+/// Marks the `Span` holding a line's leading whitespace
+#[derive(Component, Reflect)]
+pub struct Indent;
+
+/// One extended grapheme cluster, so accents, ZWJ emoji and flags stay whole
 #[derive(Component, Reflect)]
 pub struct Character;
 
+/// Columns a `Character` takes up on screen, 2 for CJK and emoji
+#[derive(Component, Default, Deref, DerefMut, Reflect)]
+pub struct DisplayWidth(pub usize);
+
+impl DisplayWidth {
+    pub fn of(grapheme: &str) -> Self {
+        Self(grapheme.width().clamp(1, 2))
+    }
+}
+
+/// Background tints from the features that mark characters. Each only sets its
+/// own, so clearing one leaves the others showing
+#[derive(Component, Default)]
+pub struct Highlights {
+    /// The focused structure node
+    pub node: Option<Color>,
+    /// Selected with the mouse
+    pub selection: Option<Color>,
+    /// The bracket pairing with the focused one
+    pub bracket: Option<Color>,
+}
+
 #[derive(Component, Default, Deref, DerefMut, Reflect)]
 pub struct ScrollPosition(f32);
 
+/// How far a document that doesn't wrap is scrolled to the left
+#[derive(Component, Default, Deref, DerefMut, Reflect)]
+pub struct HorizontalScroll(pub f32);
+
+/// Set when the document has edits that haven't been written to disk
+#[derive(Component, Default, Deref, DerefMut, Reflect)]
+pub struct Modified(pub bool);
+
 #[derive(Bundle)]
 pub struct WindowsBundle {
     windows: AppWindow,
@@ -54,16 +121,93 @@ pub struct WindowsBundle {
     node: NodeBundle,
 }
 
+#[derive(Bundle)]
+pub struct LineBundle {
+    number: LineNumber,
+    line: Line,
+    node: NodeBundle,
+}
+
+impl LineBundle {
+    pub fn new(number: usize) -> Self {
+        Self {
+            number: LineNumber(number),
+            line: Line,
+            node: NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct SpanBundle {
+    span: Span,
+    node: NodeBundle,
+}
+
+impl Default for SpanBundle {
+    fn default() -> Self {
+        Self {
+            span: Span,
+            node: NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    // wrapped lines break between spans, never inside one
+                    flex_shrink: 0.,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct CharacterBundle {
+    character: Character,
+    width: DisplayWidth,
+    highlights: Highlights,
+    interaction: Interaction,
+    text: TextBundle,
+}
+
+impl CharacterBundle {
+    pub fn new(grapheme: &str) -> Self {
+        Self {
+            character: Character,
+            width: DisplayWidth::of(grapheme),
+            highlights: Highlights::default(),
+            interaction: Interaction::default(),
+            text: TextBundle::from_section(grapheme, Default::default()),
+        }
+    }
+
+    /// Stands in on an empty line so the cursor has somewhere to land. It holds no
+    /// text, so nothing of it is saved, and is padded out to a column on screen
+    pub fn placeholder() -> Self {
+        let mut bundle = Self::new("");
+        bundle.text.text.sections.push(TextSection::new(" ", Default::default()));
+        bundle
+    }
+}
+
 #[derive(Resource, Deref, Default)]
-pub struct WorkingFilePath(PathBuf);
+pub struct WorkingFilePath(pub PathBuf);
 
 fn setup(
     mut commands: Commands,
+    settings: Res<Settings>,
     mut file_path: ResMut<WorkingFilePath>,
 ) {
-    let path = CLI::parse().path.expect("File Required");
+    let path = Cli::parse().path.expect("File Required");
     *file_path = WorkingFilePath(path.clone());
     let content = fs::read_to_string(path.clone()).expect("File Doesn't Exist");
+    let segmenter = settings.segmenter.resolve(&path);
 
     commands.spawn(WindowsBundle {
         windows: AppWindow,
@@ -74,78 +218,310 @@ fn setup(
                 height: Val::Percent(100.0),
                 align_self: AlignSelf::Stretch,
                 flex_direction: FlexDirection::Column,
-                overflow: Overflow::clip_y(),
+                overflow: Overflow::clip(),
                 ..Default::default()
             },
             background_color: BackgroundColor::from(Color::rgb(0.1, 0.1, 0.1)),
             ..Default::default()
         },
     }).with_children(|parent| {
-        parent.spawn((
-            Document,
-            NodeBundle {
-                style: Style {
-                    flex_direction: FlexDirection::Column,
-                    ..Default::default()
-                },
+        parent.spawn(document_bundle(&path)).with_children(|parent| spawn_lines(parent, &content, segmenter));
+    });
+    commands.spawn((
+        BufferList,
+        NodeBundle {
+            style: Style { display: Display::None, ..Default::default() },
+            ..Default::default()
+        },
+    ));
+}
+
+fn document_bundle(path: &Path) -> impl Bundle {
+    (
+        Document,
+        DocumentPath(path.to_path_buf()),
+        NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
                 ..Default::default()
             },
-            ScrollPosition::default(),
-        )).with_children(|parent| {
-            for (i, line_str) in content.split('\n').enumerate() {
-                parent.spawn((
-                    LineNumber(i + 1),
-                    Line,
-                    NodeBundle {
-                        style: Style {
-                            flex_direction: FlexDirection::Row,
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    }
-                )).with_children(|parent| {
-                    let mut empty = true;
-                    for span_str in line_str.split_inclusive(' ') {
-                        empty = false;
-                        parent.spawn((
-                            Span,
-                            NodeBundle {
-                                style: Style {
-                                    flex_direction: FlexDirection::Row,
-                                    ..Default::default()
-                                },
-                                ..Default::default()
-                            }
-                        )).with_children(|parent| {
-                            for ch in span_str.chars() {
-                                parent.spawn((
-                                    Character,
-                                    TextBundle::from_section(ch, Default::default())
-                                ));
-                            }
-                        });
+            ..Default::default()
+        },
+        ScrollPosition::default(),
+        HorizontalScroll::default(),
+        Wrap::default(),
+        Segmentation::default(),
+        Modified::default(),
+    )
+}
+
+pub fn spawn_lines(parent: &mut ChildBuilder, content: &str, segmenter: &dyn SpanSegmenter) {
+    for (i, line_str) in content.split('\n').enumerate() {
+        parent.spawn(LineBundle::new(i + 1)).with_children(|parent| {
+            let content_str = line_str.trim_start_matches([' ', '\t']);
+            let indent_str = &line_str[..line_str.len() - content_str.len()];
+            if !indent_str.is_empty() {
+                parent.spawn((Indent, SpanBundle::default())).with_children(|parent| {
+                    for grapheme in indent_str.graphemes(true) {
+                        parent.spawn(CharacterBundle::new(grapheme));
                     }
-                    if empty {
-                        parent.spawn((
-                            Span,
-                            NodeBundle {
-                                style: Style {
-                                    flex_direction: FlexDirection::Row,
-                                    ..Default::default()
-                                },
-                                ..Default::default()
-                            }
-                        )).with_children(|parent| {
-                            parent.spawn((
-                                Character,
-                                TextBundle::from_section(" ", Default::default())
-                            ));
-                        });
+                });
+            }
+            for span_str in segmenter.segments(content_str) {
+                parent.spawn(SpanBundle::default()).with_children(|parent| {
+                    for grapheme in span_str.graphemes(true) {
+                        parent.spawn(CharacterBundle::new(grapheme));
                     }
                 });
             }
+            if line_str.is_empty() {
+                parent.spawn(SpanBundle::default()).with_children(|parent| {
+                    parent.spawn(CharacterBundle::placeholder());
+                });
+            }
         });
-    });
+    }
+}
+
+/// The grapheme a character holds. Any later sections only pad it out on screen,
+/// like the spaces after a tab
+pub fn character_str(text: &Text) -> String {
+    text.sections
+        .first()
+        .map(|section| section.value.clone())
+        .unwrap_or_default()
+}
+
+/// Rebuilds the file contents from a document's lines
+#[allow(clippy::type_complexity)]
+pub fn document_text<F: QueryFilter>(
+    doc_children: &Children,
+    content_q: &Query<&Children, Or<(With<Line>, With<Span>)>>,
+    text_q: &Query<&Text, F>,
+) -> String {
+    let mut output = String::new();
+    for (i, line_id) in doc_children.iter().enumerate() {
+        if i > 0 { output.push('\n') }
+        let Ok(line_children) = content_q.get(*line_id) else { continue };
+        for span_id in line_children.iter() {
+            let Ok(span_children) = content_q.get(*span_id) else { continue };
+            for ch_id in span_children.iter() {
+                if let Ok(character) = text_q.get(*ch_id) {
+                    output.push_str(&character_str(character));
+                }
+            }
+        }
+    }
+    output
+}
+
+/// Every `Character` of a line, left to right
+pub fn line_characters(line_id: Entity, children_q: &Query<&Children>) -> Vec<Entity> {
+    children_q
+        .get(line_id)
+        .map(|spans| spans
+            .iter()
+            .filter_map(|span_id| children_q.get(*span_id).ok())
+            .flat_map(|chars| chars.iter().copied())
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Flags the document containing `entity` as having unsaved edits
+pub fn mark_modified(
+    entity: Entity,
+    parents_q: &Query<&Parent>,
+    modified_q: &mut Query<&mut Modified>,
+) {
+    let mut node = entity;
+    loop {
+        if let Ok(mut modified) = modified_q.get_mut(node) {
+            **modified = true;
+            return
+        }
+        let Ok(parent) = parents_q.get(node) else { return };
+        node = **parent;
+    }
+}
+
+fn paint_highlights(mut chars_q: Query<(&Highlights, &mut BackgroundColor), Changed<Highlights>>) {
+    for (highlights, mut bg) in chars_q.iter_mut() {
+        // the narrower highlights show over the wider ones
+        let color = highlights.bracket.or(highlights.selection).or(highlights.node);
+        *bg = BackgroundColor(color.unwrap_or(Color::NONE));
+    }
+}
+
+/// Gives an emptied line a placeholder character, in a span of its own
+pub fn spawn_placeholder(commands: &mut Commands, line_id: Entity) -> Entity {
+    let placeholder = commands.spawn(CharacterBundle::placeholder()).id();
+    let span = commands.spawn(SpanBundle::default()).add_child(placeholder).id();
+    commands.entity(line_id).add_child(span);
+    placeholder
+}
+
+/// Despawns characters, along with any span they leave empty
+pub fn remove_characters(
+    commands: &mut Commands,
+    char_ids: &[Entity],
+    parents_q: &Query<&Parent>,
+    children_q: &Query<&Children>,
+) {
+    for char_id in char_ids {
+        commands.entity(*char_id).despawn_recursive();
+    }
+    let mut spans = char_ids
+        .iter()
+        .filter_map(|char_id| parents_q.get(*char_id).ok())
+        .map(|span_id| **span_id)
+        .collect::<Vec<_>>();
+    spans.dedup();
+    for span_id in spans {
+        let emptied = children_q
+            .get(span_id)
+            .map_or(true, |children| children.iter().all(|child| char_ids.contains(child)));
+        if emptied {
+            commands.entity(span_id).despawn_recursive();
+        }
+    }
+}
+
+fn renumber_lines(
+    doc_q: Query<&Children, (With<Document>, Changed<Children>)>,
+    mut numbers_q: Query<&mut LineNumber>,
+) {
+    for doc_children in doc_q.iter() {
+        for (i, line_id) in doc_children.iter().enumerate() {
+            if let Ok(mut number) = numbers_q.get_mut(*line_id) {
+                if **number != i + 1 {
+                    **number = i + 1;
+                }
+            }
+        }
+    }
+}
+
+/// Swaps out every line of a document, e.g. when recovering or reloading it
+#[derive(Event)]
+pub struct ReplaceContent {
+    pub document: Entity,
+    pub content: String,
+}
+
+fn replace_content(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    file_path: Res<WorkingFilePath>,
+    mut replace_evr: EventReader<ReplaceContent>,
+    doc_q: Query<(&Segmentation, &DocumentPath)>,
+) {
+    for ReplaceContent { document, content } in replace_evr.read() {
+        let segmenter = match doc_q.get(*document) {
+            Ok((segmentation, path)) => segmentation.get(&settings).resolve(path),
+            Err(_) => settings.segmenter.resolve(&file_path),
+        };
+        commands.entity(*document)
+            .despawn_descendants()
+            .with_children(|parent| spawn_lines(parent, content, segmenter));
+    }
+}
+
+/// Shows a file in the window, reading it into a new buffer unless one has it already
+#[derive(Event)]
+pub struct OpenFile {
+    pub path: PathBuf,
+    /// Line and character to focus, counted from 0, instead of the start of the file
+    pub position: Option<(usize, usize)>,
+    /// Goes there as a `LongJump`, so the jump list can come back, for marks and grep matches
+    pub record_jump: bool,
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn open_file(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    settings: Res<Settings>,
+    mut file_path: ResMut<WorkingFilePath>,
+    mut status: ResMut<StatusMessage>,
+    mut open_evr: EventReader<OpenFile>,
+    mut refocus_evw: EventWriter<Refocus>,
+    mut jump_evw: EventWriter<LongJump>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    mut opened: Local<Option<(Entity, Option<(usize, usize)>, bool)>>,
+    window_q: Query<(Entity, &Children), (With<AppWindow>, Without<Parent>, Without<Explorer>)>,
+    buffers_q: Query<Entity, With<BufferList>>,
+    docs_q: Query<(Entity, &DocumentPath)>,
+    children_q: Query<&Children>,
+) {
+    // a new buffer's lines are only there the frame after it's opened
+    if let Some((document, position, record_jump)) = opened.take() {
+        let lines = children_q.get(document).map_or(&[][..], |lines| &lines[..]);
+        let target = match (state.get(), position) {
+            (_, Some((line, column))) => lines
+                .get(line)
+                .or(lines.last())
+                .and_then(|line_id| {
+                    let line_chars = line_characters(*line_id, &children_q);
+                    line_chars.get(column).or(line_chars.last()).copied()
+                }),
+            (AppState::Travel, None) => Some(document),
+            (_, None) => lines
+                .first()
+                .and_then(|line_id| line_characters(*line_id, &children_q).first().copied()),
+        };
+        match (target, record_jump) {
+            (Some(target), true) => {
+                jump_evw.send(LongJump(target));
+                // the jump list handles it in a later system, maybe next frame
+                redraw_evw.send(RequestRedraw);
+            },
+            (Some(target), false) => { refocus_evw.send(Refocus(target)); },
+            (None, _) => (),
+        }
+    }
+
+    let Some(OpenFile { path, position, record_jump }) = open_evr.read().last() else { return };
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let wanted = canonical(path);
+    let (window, shown) = window_q.single();
+    let current = shown.iter().copied().find(|id| docs_q.contains(*id));
+    let existing = docs_q
+        .iter()
+        .find(|(_, doc_path)| canonical(doc_path) == wanted)
+        .map(|(document, _)| document);
+    if existing.is_some() && existing == current {
+        if let Some(document) = current.filter(|_| position.is_some()) {
+            *opened = Some((document, *position, *record_jump));
+            redraw_evw.send(RequestRedraw);
+        }
+        return
+    }
+
+    let document = match existing {
+        Some(document) => document,
+        None => {
+            let content = match fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(err) => {
+                    **status = Some(format!("Can't open {}: {err}", path.display()));
+                    return
+                },
+            };
+            let segmenter = settings.segmenter.resolve(path);
+            commands.spawn((document_bundle(path), DiskStamp(FileStamp::read(path))))
+                .with_children(|parent| spawn_lines(parent, &content, segmenter))
+                .id()
+        },
+    };
+    if let Some(current) = current {
+        commands.entity(current).insert(Background);
+        commands.entity(buffers_q.single()).add_child(current);
+    }
+    commands.entity(document).remove::<Background>();
+    commands.entity(window).add_child(document);
+    *file_path = WorkingFilePath(path.clone());
+    *opened = Some((document, *position, *record_jump));
+    redraw_evw.send(RequestRedraw);
 }
 
 #[derive(Event)]
@@ -173,10 +549,11 @@ pub fn scroll(
 fn mouse_scroll(
     mut scrollwheel_evr: EventReader<MouseWheel>,
     mut scroll_evw: EventWriter<Scroll>,
+    settings: Res<Settings>,
 ) {
     for mouse_wheel_event in scrollwheel_evr.read() {
         scroll_evw.send(Scroll(match mouse_wheel_event.unit {
-            MouseScrollUnit::Line => mouse_wheel_event.y * 20.,
+            MouseScrollUnit::Line => mouse_wheel_event.y * settings.wheel_multiplier,
             MouseScrollUnit::Pixel => mouse_wheel_event.y,
         }));
     }
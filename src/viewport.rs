@@ -0,0 +1,237 @@
+use bevy::{prelude::*, window::RequestRedraw};
+
+use crate::keymap::Action;
+use crate::marks::Mark;
+use crate::settings::Settings;
+use crate::text_components::{line_characters, DisplayWidth, HorizontalScroll, ScrollPosition};
+use crate::structure::StructureNode;
+use crate::wrap::Wrap;
+use crate::{CurrentZipper, JumpToChar, ZipperFocus, ZipperType};
+
+pub struct ViewportPlugin;
+
+impl Plugin for ViewportPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (view_commands, follow_cursor).chain());
+    }
+}
+
+/// Where the cursor's row sits in its document and which part of the document shows, in pixels
+struct View {
+    document: Entity,
+    row_top: f32,
+    row_height: f32,
+    view_top: f32,
+    view_height: f32,
+    max_top: f32,
+}
+
+impl View {
+    fn of(
+        mark: &Mark,
+        parents_q: &Query<&Parent>,
+        nodes_q: &Query<(&Node, &GlobalTransform)>,
+        docs_q: &Query<(&mut ScrollPosition, &mut HorizontalScroll, &mut Style)>,
+    ) -> Option<Self> {
+        let document = **parents_q.get(mark.line_id).ok()?;
+        let container = **parents_q.get(document).ok()?;
+        let top = |entity: Entity| nodes_q
+            .get(entity)
+            .ok()
+            .map(|(node, transform)| (transform.translation().y - node.size().y / 2., node.size().y));
+        // the row rather than the line, which spans several when it wraps
+        let (row_top, row_height) = top(mark.char_id)?;
+        let (doc_top, doc_height) = top(document)?;
+        let (_, view_height) = top(container)?;
+        let (scroll_pos, ..) = docs_q.get(document).ok()?;
+        Some(Self {
+            document,
+            row_top: row_top - doc_top,
+            row_height,
+            view_top: -**scroll_pos,
+            view_height,
+            max_top: (doc_height - view_height).max(0.),
+        })
+    }
+
+    /// `scrolloff` lines, as long as they leave room for the cursor line
+    fn margin(&self, settings: &Settings) -> f32 {
+        let most = ((self.view_height - self.row_height) / 2.).max(0.);
+        (settings.scrolloff as f32 * self.row_height).min(most)
+    }
+
+    fn scroll_to(&self, view_top: f32, docs_q: &mut Query<(&mut ScrollPosition, &mut HorizontalScroll, &mut Style)>) {
+        let Ok((mut scroll_pos, _, mut style)) = docs_q.get_mut(self.document) else { return };
+        **scroll_pos = -view_top.clamp(0., self.max_top);
+        style.top = Val::Px(**scroll_pos);
+    }
+}
+
+/// Columns from the start of the line to a character
+fn column_of(char_id: Entity, line_id: Entity, children_q: &Query<&Children>, widths_q: &Query<&DisplayWidth>) -> usize {
+    line_characters(line_id, children_q)
+        .iter()
+        .take_while(|id| **id != char_id)
+        .map(|id| widths_q.get(*id).map_or(1, |width| **width))
+        .sum()
+}
+
+/// The character covering `column`, or the last one on a shorter line
+fn char_at_column(line_id: Entity, column: usize, children_q: &Query<&Children>, widths_q: &Query<&DisplayWidth>) -> Option<Entity> {
+    let line_chars = line_characters(line_id, children_q);
+    let mut col = 0;
+    for char_id in line_chars.iter() {
+        col += widths_q.get(*char_id).map_or(1, |width| **width);
+        if col > column {
+            return Some(*char_id)
+        }
+    }
+    line_chars.last().copied()
+}
+
+/// Scrolls just enough to keep the cursor `scrolloff` lines from the edges,
+/// or centers it when it lands more than a screen away. Documents that don't
+/// wrap scroll sideways the same way, by `sidescrolloff` columns
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn follow_cursor(
+    settings: Res<Settings>,
+    mut waiting: Local<Option<Mark>>,
+    mut redraw_evw: EventWriter<RequestRedraw>,
+    curr_zipp_q: Query<
+        (&ZipperType, &ZipperFocus),
+        (With<CurrentZipper>, Or<(Added<CurrentZipper>, Changed<ZipperFocus>)>),
+    >,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    wrap_q: Query<&Wrap>,
+    node_q: Query<&StructureNode>,
+    nodes_q: Query<(&Node, &GlobalTransform)>,
+    mut docs_q: Query<(&mut ScrollPosition, &mut HorizontalScroll, &mut Style)>,
+) {
+    // nodes are followed by their first character
+    let node_mark = |focus: &ZipperFocus| node_q
+        .get(**focus)
+        .ok()
+        .and_then(|node| node.chars.first())
+        .and_then(|char_id| Some(Mark {
+            char_id: *char_id,
+            line_id: **parents_q.get(**parents_q.get(*char_id).ok()?).ok()?,
+        }));
+    let moved = curr_zipp_q
+        .get_single()
+        .ok()
+        .and_then(|zipper| Mark::focused(zipper, &parents_q, &children_q).or_else(|| node_mark(zipper.1)));
+    let Some(mark) = moved.or(waiting.take()) else { return };
+    let Some(view) = View::of(&mark, &parents_q, &nodes_q, &docs_q) else { return };
+    // a line that was just added hasn't been laid out yet
+    if view.row_height == 0. {
+        *waiting = Some(mark);
+        redraw_evw.send(RequestRedraw);
+        return
+    }
+
+    let margin = view.margin(&settings);
+    let row_bottom = view.row_top + view.row_height;
+    let far = view.row_top < view.view_top - view.view_height
+        || row_bottom > view.view_top + 2. * view.view_height;
+    let view_top = if far {
+        Some(view.row_top + view.row_height / 2. - view.view_height / 2.)
+    } else if view.row_top < view.view_top + margin {
+        Some(view.row_top - margin)
+    } else if row_bottom > view.view_top + view.view_height - margin {
+        Some(row_bottom + margin - view.view_height)
+    } else {
+        None
+    };
+    if let Some(view_top) = view_top {
+        view.scroll_to(view_top, &mut docs_q);
+    }
+
+    if wrap_q.get(view.document).map_or(true, |wrap| wrap.is_on(&settings)) { return }
+    let Ok(container) = parents_q.get(view.document) else { return };
+    let left_of = |entity: Entity| nodes_q
+        .get(entity)
+        .ok()
+        .map(|(node, transform)| (transform.translation().x - node.size().x / 2., node.size().x));
+    let (Some((char_left, char_width)), Some((doc_left, _)), Some((_, view_width))) =
+        (left_of(mark.char_id), left_of(view.document), left_of(**container)) else { return };
+    let Some((end_left, end_width)) = line_characters(mark.line_id, &children_q)
+        .last()
+        .and_then(|last_id| left_of(*last_id)) else { return };
+    let Ok((_, mut scroll, mut style)) = docs_q.get_mut(view.document) else { return };
+
+    let char_left = char_left - doc_left;
+    let char_right = char_left + char_width;
+    let side_margin = (settings.sidescrolloff as f32 * char_width).min(((view_width - char_width) / 2.).max(0.));
+    let max_left = (end_left - doc_left + end_width + side_margin - view_width).max(0.);
+    let view_left = if char_left < **scroll + side_margin {
+        char_left - side_margin
+    } else if char_right > **scroll + view_width - side_margin {
+        char_right + side_margin - view_width
+    } else {
+        return
+    };
+    **scroll = view_left.clamp(0., max_left);
+    style.left = Val::Px(-**scroll);
+}
+
+/// `zz`/`zt`/`zb` and the page motions
+#[allow(clippy::too_many_arguments)]
+fn view_commands(
+    settings: Res<Settings>,
+    mut action_evr: EventReader<Action>,
+    mut jump_evw: EventWriter<JumpToChar>,
+    curr_zipp_q: Query<(&ZipperType, &ZipperFocus), With<CurrentZipper>>,
+    parents_q: Query<&Parent>,
+    children_q: Query<&Children>,
+    widths_q: Query<&DisplayWidth>,
+    nodes_q: Query<(&Node, &GlobalTransform)>,
+    mut docs_q: Query<(&mut ScrollPosition, &mut HorizontalScroll, &mut Style)>,
+) {
+    for action in action_evr.read() {
+        let (lines, down) = match action {
+            Action::CenterView | Action::ViewTop | Action::ViewBottom => (0, true),
+            Action::HalfPageDown | Action::PageDown => (1, true),
+            Action::HalfPageUp | Action::PageUp => (1, false),
+            _ => continue,
+        };
+        let Some(mark) = Mark::focused(curr_zipp_q.single(), &parents_q, &children_q) else { continue };
+        let Some(view) = View::of(&mark, &parents_q, &nodes_q, &docs_q) else { continue };
+        let margin = view.margin(&settings);
+        match action {
+            Action::CenterView => {
+                view.scroll_to(view.row_top + view.row_height / 2. - view.view_height / 2., &mut docs_q);
+            },
+            Action::ViewTop => view.scroll_to(view.row_top - margin, &mut docs_q),
+            Action::ViewBottom => {
+                view.scroll_to(view.row_top + view.row_height + margin - view.view_height, &mut docs_q);
+            },
+            _ if lines > 0 && view.row_height > 0. => {
+                // the cursor moves by as many lines as the view scrolls
+                let page = (view.view_height / view.row_height).floor() as usize;
+                let lines = match action {
+                    Action::HalfPageDown | Action::HalfPageUp => page / 2,
+                    _ => page.saturating_sub(2),
+                }.max(1);
+                let distance = lines as f32 * view.row_height;
+                let view_top = match down {
+                    true => view.view_top + distance,
+                    false => view.view_top - distance,
+                };
+                view.scroll_to(view_top, &mut docs_q);
+
+                let Ok(doc_lines) = children_q.get(view.document) else { continue };
+                let Some(index) = doc_lines.iter().position(|id| *id == mark.line_id) else { continue };
+                let index = match down {
+                    true => (index + lines).min(doc_lines.len() - 1),
+                    false => index.saturating_sub(lines),
+                };
+                let column = column_of(mark.char_id, mark.line_id, &children_q, &widths_q);
+                if let Some(target) = char_at_column(doc_lines[index], column, &children_q, &widths_q) {
+                    jump_evw.send(JumpToChar(target));
+                }
+            },
+            _ => (),
+        }
+    }
+}
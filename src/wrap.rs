@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+
+use crate::settings::Settings;
+use crate::text_components::{Document, HorizontalScroll, Line, Span};
+
+/// Room left of wrapped lines for the continuation markers, in font sizes
+const GUTTER: f32 = 0.5;
+const MARKER_WIDTH: f32 = 2.;
+
+pub struct WrapPlugin;
+
+impl Plugin for WrapPlugin {
+    fn build(&self, appl: &mut App) {
+        appl.add_systems(Update, (layout_lines, place_markers).chain());
+    }
+}
+
+/// Whether a document's long lines wrap, `None` following the `wrap` setting
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Wrap(pub Option<bool>);
+
+impl Wrap {
+    pub fn is_on(&self, settings: &Settings) -> bool {
+        self.unwrap_or(settings.wrap)
+    }
+}
+
+/// Drawn left of each row a wrapped line continues on
+#[derive(Component)]
+struct WrapMarker;
+
+/// Soft wrapped lines break between spans, the rest run on and scroll sideways
+#[allow(clippy::type_complexity)]
+fn layout_lines(
+    settings: Res<Settings>,
+    mut doc_q: Query<(Ref<Wrap>, &Children, &mut HorizontalScroll, &mut Style), (With<Document>, Without<Line>)>,
+    new_lines_q: Query<(), Added<Line>>,
+    mut line_q: Query<&mut Style, (With<Line>, Without<Document>)>,
+) {
+    let relayout = settings.is_changed() || !new_lines_q.is_empty();
+    for (wrap, lines, mut scroll, mut doc_style) in doc_q.iter_mut() {
+        if !relayout && !wrap.is_changed() { continue }
+        let wrapping = wrap.is_on(&settings);
+        if wrapping && **scroll != 0. {
+            **scroll = 0.;
+            doc_style.left = Val::Px(0.);
+        }
+        for line_id in lines.iter() {
+            let Ok(mut style) = line_q.get_mut(*line_id) else { continue };
+            let (flex_wrap, gutter) = match wrapping {
+                true => (FlexWrap::Wrap, Val::Px(settings.font_size * GUTTER)),
+                false => (FlexWrap::NoWrap, Val::ZERO),
+            };
+            if style.flex_wrap != flex_wrap || style.padding.left != gutter {
+                style.flex_wrap = flex_wrap;
+                style.padding.left = gutter;
+            }
+        }
+    }
+}
+
+/// Puts a marker in the gutter of every row after the first of a wrapped line
+fn place_markers(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    moved_q: Query<(), (With<Span>, Changed<GlobalTransform>)>,
+    doc_q: Query<(&Wrap, &Children), With<Document>>,
+    children_q: Query<&Children>,
+    nodes_q: Query<(&Node, &GlobalTransform)>,
+    mut marker_q: Query<(Entity, &mut Style, &mut BackgroundColor), With<WrapMarker>>,
+) {
+    if moved_q.is_empty() && !settings.is_changed() { return }
+    let mut rows = vec![];
+    for (wrap, lines) in doc_q.iter() {
+        if !wrap.is_on(&settings) { continue }
+        for line_id in lines.iter() {
+            let Ok((line_node, line_transform)) = nodes_q.get(*line_id) else { continue };
+            let line_left = line_transform.translation().x - line_node.size().x / 2.;
+            let Ok(spans) = children_q.get(*line_id) else { continue };
+            let mut row_top = None;
+            for span_id in spans.iter() {
+                let Ok((node, transform)) = nodes_q.get(*span_id) else { continue };
+                let top = transform.translation().y - node.size().y / 2.;
+                match row_top {
+                    Some(row) if top > row + 0.5 => {
+                        rows.push((line_left, top, node.size().y));
+                        row_top = Some(top);
+                    },
+                    Some(_) => (),
+                    None => row_top = Some(top),
+                }
+            }
+        }
+    }
+
+    let color = BackgroundColor(settings.foreground.with_a(0.4));
+    let gutter = settings.font_size * GUTTER;
+    let mut markers = marker_q.iter_mut();
+    for (left, top, height) in rows {
+        let style = Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(left + (gutter - MARKER_WIDTH) / 2.),
+            top: Val::Px(top),
+            width: Val::Px(MARKER_WIDTH),
+            height: Val::Px(height),
+            ..Default::default()
+        };
+        match markers.next() {
+            Some((_, mut marker_style, mut marker_color)) => {
+                *marker_style = style;
+                *marker_color = color;
+            },
+            None => { commands.spawn((WrapMarker, NodeBundle { style, background_color: color, ..Default::default() })); },
+        }
+    }
+    for (marker_id, ..) in markers {
+        commands.entity(marker_id).despawn_recursive();
+    }
+}